@@ -0,0 +1,1961 @@
+//! CPU reference renderer.
+//!
+//! Computes the same escape-time iteration and colouring as the double-single
+//! and 64-bit shader variants, so images can be produced and compared without a GPU.
+//! Every function here mirrors its WGSL counterpart of the same name.
+
+use std::thread;
+
+use crate::{
+    expression::{Expression, Function, Node, Operator},
+    histogram::HISTOGRAM_BINS,
+    formula::{FOLD_ABS_X, FOLD_ABS_Y, FOLD_CONJUGATE, FOLD_ABS_RE},
+    interactive::camera_controller::{Properties, Properties32, MATH32, PERTURBATION, DOUBLE_DOUBLE, COLOURING_ITERATIONS, COLOURING_SMOOTH, COLOURING_DISTANCE, COLOURING_HISTOGRAM, COLOURING_TRAP_DISTANCE, COLOURING_TRAP_ITERATION, COLOURING_STRIPE, COLOURING_TRIANGLE, COLOURING_CURVATURE, SAMPLING_JITTERED, SAMPLING_ADAPTIVE},
+    newton::{Polynomial, Polynomial32},
+    palette::{Palette, INTERPOLATION_SMOOTH, INTERPOLATION_STEP},
+    trap::{TRAP_LINE, TRAP_CROSS, TRAP_CIRCLE, TRAP_STALK},
+    perturbation::{ReferenceOrbit, SeriesApproximation, SERIES_TOLERANCE32, SERIES_TOLERANCE64},
+};
+
+type Complex32 = [f32; 2];
+type Complex64 = [f64; 2];
+
+fn mul32(a: Complex32, b: Complex32) -> Complex32 {
+    [a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]]
+}
+
+fn div32(a: Complex32, b: Complex32) -> Complex32 {
+    let product = mul32(a, [b[0], -b[1]]);
+    let abs2 = b[0] * b[0] + b[1] * b[1];
+    [product[0] / abs2, product[1] / abs2]
+}
+
+fn mul64(a: Complex64, b: Complex64) -> Complex64 {
+    [a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]]
+}
+
+fn pow32(z: Complex32, e: f32) -> Complex32 {
+    if e == e.floor() {
+        let mut p = [1.0, 0.0];
+        for _ in 0..e as u32 {
+            p = mul32(p, z);
+        }
+        return p;
+    }
+    let r = (z[0] * z[0] + z[1] * z[1]).powf(0.5 * e);
+    let theta = z[1].atan2(z[0]) * e;
+    [r * theta.cos(), r * theta.sin()]
+}
+
+fn exp32(z: Complex32) -> Complex32 {
+    let r = z[0].exp();
+    [r * z[1].cos(), r * z[1].sin()]
+}
+
+fn log32(z: Complex32) -> Complex32 {
+    [0.5 * (z[0] * z[0] + z[1] * z[1]).ln(), z[1].atan2(z[0])]
+}
+
+fn sqrt32(z: Complex32) -> Complex32 {
+    pow32(z, 0.5)
+}
+
+fn sin32(z: Complex32) -> Complex32 {
+    [z[0].sin() * z[1].cosh(), z[0].cos() * z[1].sinh()]
+}
+
+fn cos32(z: Complex32) -> Complex32 {
+    [z[0].cos() * z[1].cosh(), -z[0].sin() * z[1].sinh()]
+}
+
+fn sinh32(z: Complex32) -> Complex32 {
+    [z[0].sinh() * z[1].cos(), z[0].cosh() * z[1].sin()]
+}
+
+fn cosh32(z: Complex32) -> Complex32 {
+    [z[0].cosh() * z[1].cos(), z[0].sinh() * z[1].sin()]
+}
+
+fn add32(a: Complex32, b: Complex32) -> Complex32 {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn sub32(a: Complex32, b: Complex32) -> Complex32 {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn scale32(s: f32, a: Complex32) -> Complex32 {
+    [s * a[0], s * a[1]]
+}
+
+/// Value and derivative of `node` at `w` with derivative `dw`, computed
+/// the same way as by the code `Expression::wgsl` generates for it.
+fn custom32(node: &Node, w: Complex32, dw: Complex32, c: Complex32, dc: Complex32) -> (Complex32, Complex32) {
+    let evaluate = |node: &Node| custom32(node, w, dw, c, dc);
+    match node {
+        Node::Z => (w, dw),
+        Node::C => (c, dc),
+        Node::Number(number) => (*number, [0.0, 0.0]),
+        Node::Neg(a) => {
+            let (va, da) = evaluate(a);
+            (scale32(-1.0, va), scale32(-1.0, da))
+        }
+        Node::Binary(operator, a, b) => {
+            let (va, da) = evaluate(a);
+            let (vb, db) = evaluate(b);
+            match operator {
+                Operator::Add => (add32(va, vb), add32(da, db)),
+                Operator::Sub => (sub32(va, vb), sub32(da, db)),
+                Operator::Mul => (mul32(va, vb), add32(mul32(da, vb), mul32(va, db))),
+                Operator::Div => (div32(va, vb), div32(sub32(mul32(da, vb), mul32(va, db)), mul32(vb, vb))),
+                Operator::Pow => match b.real_constant() {
+                    Some(0.0) => ([1.0, 0.0], [0.0, 0.0]),
+                    Some(e) if e > 0.0 && e == e.round() => (pow32(va, e), scale32(e, mul32(pow32(va, e - 1.0), da))),
+                    Some(e) => {
+                        let v = if e == e.round() { div32([1.0, 0.0], pow32(va, -e)) } else { pow32(va, e) };
+                        (v, scale32(e, mul32(v, div32(da, va))))
+                    }
+                    None => {
+                        let v = exp32(mul32(vb, log32(va)));
+                        (v, mul32(v, add32(mul32(db, log32(va)), mul32(vb, div32(da, va)))))
+                    }
+                },
+            }
+        }
+        Node::Call(function, a) => {
+            let (va, da) = evaluate(a);
+            match function {
+                Function::Sin => (sin32(va), mul32(cos32(va), da)),
+                Function::Cos => (cos32(va), scale32(-1.0, mul32(sin32(va), da))),
+                Function::Tan => (div32(sin32(va), cos32(va)), div32(da, mul32(cos32(va), cos32(va)))),
+                Function::Sinh => (sinh32(va), mul32(cosh32(va), da)),
+                Function::Cosh => (cosh32(va), mul32(sinh32(va), da)),
+                Function::Tanh => (div32(sinh32(va), cosh32(va)), div32(da, mul32(cosh32(va), cosh32(va)))),
+                Function::Exp => {
+                    let v = exp32(va);
+                    (v, mul32(v, da))
+                }
+                Function::Log => (log32(va), div32(da, va)),
+                Function::Sqrt => {
+                    let v = sqrt32(va);
+                    (v, div32(da, scale32(2.0, v)))
+                }
+            }
+        }
+    }
+}
+
+fn custom_step32(properties: &Uniforms, z: &mut Complex32, der: &mut Complex32, c: Complex32, dc: Complex32) {
+    (*z, *der) = custom32(properties.custom_formula.root(), *z, *der, c, dc);
+}
+
+fn pow64(z: Complex64, e: u32) -> Complex64 {
+    let mut p = [1.0, 0.0];
+    for _ in 0..e {
+        p = mul64(p, z);
+    }
+    p
+}
+
+fn length32(a: Complex32) -> f32 {
+    (a[0] * a[0] + a[1] * a[1]).sqrt()
+}
+
+fn length64(a: Complex64) -> f64 {
+    (a[0] * a[0] + a[1] * a[1]).sqrt()
+}
+
+
+type Ds = [f32; 2];
+
+#[derive(Clone, Copy)]
+struct ComplexDs {
+    x: Ds,
+    y: Ds,
+}
+
+fn quick_two_sum(a: f32, b: f32) -> Ds {
+    let s = a + b;
+    [s, b - (s - a)]
+}
+
+fn two_sum(a: f32, b: f32) -> Ds {
+    let s = a + b;
+    let v = s - a;
+    [s, (a - (s - v)) + (b - v)]
+}
+
+fn split(a: f32) -> [f32; 2] {
+    let t = 4097.0 * a;
+    let hi = t - (t - a);
+    [hi, a - hi]
+}
+
+fn two_prod(a: f32, b: f32) -> Ds {
+    let p = a * b;
+    let a_ = split(a);
+    let b_ = split(b);
+    let e = ((a_[0] * b_[0] - p) + a_[0] * b_[1] + a_[1] * b_[0]) + a_[1] * b_[1];
+    [p, e]
+}
+
+fn add_ds(a: Ds, b: Ds) -> Ds {
+    let mut s = two_sum(a[0], b[0]);
+    let t = two_sum(a[1], b[1]);
+    s[1] += t[0];
+    s = quick_two_sum(s[0], s[1]);
+    s[1] += t[1];
+    quick_two_sum(s[0], s[1])
+}
+
+fn sub_ds(a: Ds, b: Ds) -> Ds {
+    add_ds(a, [-b[0], -b[1]])
+}
+
+fn mul_ds(a: Ds, b: Ds) -> Ds {
+    let mut p = two_prod(a[0], b[0]);
+    p[1] += a[0] * b[1] + a[1] * b[0];
+    quick_two_sum(p[0], p[1])
+}
+
+fn mulds(a: ComplexDs, b: ComplexDs) -> ComplexDs {
+    ComplexDs {
+        x: sub_ds(mul_ds(a.x, b.x), mul_ds(a.y, b.y)),
+        y: add_ds(mul_ds(a.x, b.y), mul_ds(a.y, b.x)),
+    }
+}
+
+fn powds(z: ComplexDs, e: u32) -> ComplexDs {
+    let mut p = ComplexDs { x: [1.0, 0.0], y: [0.0, 0.0] };
+    for _ in 0..e {
+        p = mulds(p, z);
+    }
+    p
+}
+
+type Dd = [f64; 2];
+
+#[derive(Clone, Copy)]
+struct ComplexDd {
+    x: Dd,
+    y: Dd,
+}
+
+fn quick_two_sum64(a: f64, b: f64) -> Dd {
+    let s = a + b;
+    [s, b - (s - a)]
+}
+
+fn two_sum64(a: f64, b: f64) -> Dd {
+    let s = a + b;
+    let v = s - a;
+    [s, (a - (s - v)) + (b - v)]
+}
+
+fn split64(a: f64) -> [f64; 2] {
+    let t = 134217729.0 * a;
+    let hi = t - (t - a);
+    [hi, a - hi]
+}
+
+fn two_prod64(a: f64, b: f64) -> Dd {
+    let p = a * b;
+    let a_ = split64(a);
+    let b_ = split64(b);
+    let e = ((a_[0] * b_[0] - p) + a_[0] * b_[1] + a_[1] * b_[0]) + a_[1] * b_[1];
+    [p, e]
+}
+
+fn add_dd(a: Dd, b: Dd) -> Dd {
+    let mut s = two_sum64(a[0], b[0]);
+    let t = two_sum64(a[1], b[1]);
+    s[1] += t[0];
+    s = quick_two_sum64(s[0], s[1]);
+    s[1] += t[1];
+    quick_two_sum64(s[0], s[1])
+}
+
+fn sub_dd(a: Dd, b: Dd) -> Dd {
+    add_dd(a, [-b[0], -b[1]])
+}
+
+fn mul_dd(a: Dd, b: Dd) -> Dd {
+    let mut p = two_prod64(a[0], b[0]);
+    p[1] += a[0] * b[1] + a[1] * b[0];
+    quick_two_sum64(p[0], p[1])
+}
+
+fn muldd(a: ComplexDd, b: ComplexDd) -> ComplexDd {
+    ComplexDd {
+        x: sub_dd(mul_dd(a.x, b.x), mul_dd(a.y, b.y)),
+        y: add_dd(mul_dd(a.x, b.y), mul_dd(a.y, b.x)),
+    }
+}
+
+fn powdd(z: ComplexDd, e: u32) -> ComplexDd {
+    let mut p = ComplexDd { x: [1.0, 0.0], y: [0.0, 0.0] };
+    for _ in 0..e {
+        p = muldd(p, z);
+    }
+    p
+}
+
+fn fold32(properties: &Uniforms, z: Complex32) -> Complex32 {
+    let mut z = z;
+    if properties.fold & FOLD_ABS_X != 0 { z[0] = z[0].abs(); }
+    if properties.fold & FOLD_ABS_Y != 0 { z[1] = z[1].abs(); }
+    if properties.fold & FOLD_CONJUGATE != 0 { z[1] = -z[1]; }
+    z
+}
+
+fn fold64(properties: &Uniforms, z: Complex64) -> Complex64 {
+    let mut z = z;
+    if properties.fold & FOLD_ABS_X != 0 { z[0] = z[0].abs(); }
+    if properties.fold & FOLD_ABS_Y != 0 { z[1] = z[1].abs(); }
+    if properties.fold & FOLD_CONJUGATE != 0 { z[1] = -z[1]; }
+    z
+}
+
+fn fold_derivative32(properties: &Uniforms, z: Complex32, der: Complex32) -> Complex32 {
+    let mut der = der;
+    if properties.fold & FOLD_ABS_X != 0 && z[0] < 0.0 { der[0] = -der[0]; }
+    if properties.fold & FOLD_ABS_Y != 0 && z[1] < 0.0 { der[1] = -der[1]; }
+    if properties.fold & FOLD_CONJUGATE != 0 { der[1] = -der[1]; }
+    der
+}
+
+fn fold_derivative64(properties: &Uniforms, z: Complex64, der: Complex64) -> Complex64 {
+    let mut der = der;
+    if properties.fold & FOLD_ABS_X != 0 && z[0] < 0.0 { der[0] = -der[0]; }
+    if properties.fold & FOLD_ABS_Y != 0 && z[1] < 0.0 { der[1] = -der[1]; }
+    if properties.fold & FOLD_CONJUGATE != 0 { der[1] = -der[1]; }
+    der
+}
+
+/// `z -> fold(z)^d`, with the absolute value of the real part if the formula takes it,
+/// and `der` -> the derivative of that times `der`.
+fn step32(properties: &Uniforms, z: &mut Complex32, der: &mut Complex32) {
+    let w = fold32(properties, *z);
+    let p = pow32(w, properties.exponent - 1.0);
+    *der = mul32(p, fold_derivative32(properties, *z, *der)).map(|d| properties.exponent * d);
+    *z = mul32(p, w);
+    if properties.fold & FOLD_ABS_RE != 0 && z[0] < 0.0 {
+        z[0] = -z[0];
+        der[0] = -der[0];
+    }
+}
+
+fn step64(properties: &Uniforms, z: &mut Complex64, der: &mut Complex64) {
+    let w = fold64(properties, *z);
+    let p = pow64(w, properties.exponent as u32 - 1);
+    *der = mul64(p, fold_derivative64(properties, *z, *der)).map(|d| properties.exponent as f64 * d);
+    *z = mul64(p, w);
+    if properties.fold & FOLD_ABS_RE != 0 && z[0] < 0.0 {
+        z[0] = -z[0];
+        der[0] = -der[0];
+    }
+}
+
+fn foldds(properties: &Uniforms, z: ComplexDs) -> ComplexDs {
+    let mut z = z;
+    if properties.fold & FOLD_ABS_X != 0 && z.x[0] < 0.0 { z.x = z.x.map(|x| -x); }
+    if properties.fold & FOLD_ABS_Y != 0 && z.y[0] < 0.0 { z.y = z.y.map(|y| -y); }
+    if properties.fold & FOLD_CONJUGATE != 0 { z.y = z.y.map(|y| -y); }
+    z
+}
+
+fn stepds(properties: &Uniforms, z: &mut ComplexDs, der: &mut Complex32) {
+    let w = foldds(properties, *z);
+    let p = powds(w, properties.exponent as u32 - 1);
+    let z_hi = [z.x[0], z.y[0]];
+    *der = mul32([p.x[0], p.y[0]], fold_derivative32(properties, z_hi, *der)).map(|d| properties.exponent * d);
+    *z = mulds(p, w);
+    if properties.fold & FOLD_ABS_RE != 0 && z.x[0] < 0.0 {
+        z.x = z.x.map(|x| -x);
+        der[0] = -der[0];
+    }
+}
+
+fn folddd(properties: &Uniforms, z: ComplexDd) -> ComplexDd {
+    let mut z = z;
+    if properties.fold & FOLD_ABS_X != 0 && z.x[0] < 0.0 { z.x = z.x.map(|x| -x); }
+    if properties.fold & FOLD_ABS_Y != 0 && z.y[0] < 0.0 { z.y = z.y.map(|y| -y); }
+    if properties.fold & FOLD_CONJUGATE != 0 { z.y = z.y.map(|y| -y); }
+    z
+}
+
+fn stepdd(properties: &Uniforms, z: &mut ComplexDd, der: &mut Complex64) {
+    let w = folddd(properties, *z);
+    let p = powdd(w, properties.exponent as u32 - 1);
+    let z_hi = [z.x[0], z.y[0]];
+    *der = mul64([p.x[0], p.y[0]], fold_derivative64(properties, z_hi, *der)).map(|d| properties.exponent as f64 * d);
+    *z = muldd(p, w);
+    if properties.fold & FOLD_ABS_RE != 0 && z.x[0] < 0.0 {
+        z.x = z.x.map(|x| -x);
+        der[0] = -der[0];
+    }
+}
+
+/// The uniforms the iteration and colouring read, in both shader variants.
+struct Uniforms {
+    zoom: f64,
+    width: f32,
+    height: f32,
+    max_iter: u32,
+    bailout: f32,
+    colouring: u32,
+    interior_checks: u32,
+    julia: u32,
+    exponent: f32,
+    fold: u32,
+    julia_c: [f64; 2],
+    newton: u32,
+    polynomial: Polynomial32,
+    custom: u32,
+    custom_formula: Expression,
+    palette_offset: f32,
+    palette_scale: f32,
+    palette_interpolation: u32,
+    palette_stops: u32,
+    time: f32,
+    palette_speed: f32,
+    trap: u32,
+    trap_center: [f32; 2],
+    trap_angle: f32,
+    trap_radius: f32,
+    trap_width: f32,
+    stripe_density: f32,
+    lighting: u32,
+    light_angle: f32,
+    light_height: f32,
+    specular: f32,
+    samples: u32,
+    sampling: u32,
+    encode_srgb: u32,
+    seed: u32,
+    /// Texels of the palette texture.
+    palette: Vec<[f32; 4]>,
+}
+
+impl Uniforms {
+    /// The uniforms of `properties`, with the stops of `palette` in place of
+    /// those of the palette the properties were set up with.
+    fn new(properties: &Properties, palette: &Palette) -> Self {
+        Uniforms {
+            zoom: properties.zoom,
+            width: properties.width,
+            height: properties.height,
+            max_iter: properties.max_iter,
+            bailout: properties.bailout,
+            colouring: properties.colouring,
+            interior_checks: properties.interior_checks,
+            julia: properties.julia,
+            exponent: properties.exponent,
+            fold: properties.fold,
+            julia_c: properties.julia_c,
+            newton: properties.newton,
+            polynomial: Polynomial32::default(),
+            custom: properties.custom,
+            custom_formula: Expression::default(),
+            palette_offset: properties.palette_offset,
+            palette_scale: properties.palette_scale,
+            palette_interpolation: properties.palette_interpolation,
+            palette_stops: palette.stops().len() as u32,
+            time: properties.time,
+            palette_speed: properties.palette_speed,
+            trap: properties.trap,
+            trap_center: properties.trap_center,
+            trap_angle: properties.trap_angle,
+            trap_radius: properties.trap_radius,
+            trap_width: properties.trap_width,
+            stripe_density: properties.stripe_density,
+            lighting: properties.lighting,
+            light_angle: properties.light_angle,
+            light_height: properties.light_height,
+            specular: properties.specular,
+            samples: properties.samples,
+            sampling: properties.sampling,
+            encode_srgb: properties.encode_srgb,
+            seed: properties.seed,
+            palette: palette.texels(),
+        }
+    }
+
+    fn new32(properties: &Properties32, palette: &Palette) -> Self {
+        Uniforms {
+            zoom: properties.zoom as f64,
+            width: properties.width,
+            height: properties.height,
+            max_iter: properties.max_iter,
+            bailout: properties.bailout,
+            colouring: properties.colouring,
+            interior_checks: properties.interior_checks,
+            julia: properties.julia,
+            exponent: properties.exponent,
+            fold: properties.fold,
+            julia_c: properties.julia_c.map(f64::from),
+            newton: properties.newton,
+            polynomial: Polynomial32::default(),
+            custom: properties.custom,
+            custom_formula: Expression::default(),
+            palette_offset: properties.palette_offset,
+            palette_scale: properties.palette_scale,
+            palette_interpolation: properties.palette_interpolation,
+            palette_stops: palette.stops().len() as u32,
+            time: properties.time,
+            palette_speed: properties.palette_speed,
+            trap: properties.trap,
+            trap_center: properties.trap_center,
+            trap_angle: properties.trap_angle,
+            trap_radius: properties.trap_radius,
+            trap_width: properties.trap_width,
+            stripe_density: properties.stripe_density,
+            lighting: properties.lighting,
+            light_angle: properties.light_angle,
+            light_height: properties.light_height,
+            specular: properties.specular,
+            samples: properties.samples,
+            sampling: properties.sampling,
+            encode_srgb: properties.encode_srgb,
+            seed: properties.seed,
+            palette: palette.texels(),
+        }
+    }
+}
+
+/// Reference orbit and series approximation, as uploaded for the shaders.
+struct Orbit<T> {
+    points: Vec<T>,
+    series: Vec<T>,
+    skip: u32,
+}
+
+type Orbit32 = Orbit<Complex32>;
+type Orbit64 = Orbit<Complex64>;
+
+fn fract(x: f32) -> f32 {
+    x - x.floor()
+}
+
+fn mix(x: f32, y: f32, a: f32) -> f32 {
+    x * (1.0 - a) + y * a
+}
+
+fn srgb_to_linear(c: [f32; 3]) -> [f32; 3] {
+    c.map(|c| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) })
+}
+
+fn linear_to_srgb(c: [f32; 3]) -> [f32; 3] {
+    c.map(|c| if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 })
+}
+
+fn output(properties: &Uniforms, colour: [f32; 3]) -> [f32; 3] {
+    if properties.encode_srgb != 0 {
+        return linear_to_srgb(colour);
+    }
+    colour
+}
+
+fn hsv2rgb(c: [f32; 3]) -> [f32; 3] {
+    let k = [1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0];
+    let p = [
+        (fract(c[0] + k[0]) * 6.0 - k[3]).abs(),
+        (fract(c[0] + k[1]) * 6.0 - k[3]).abs(),
+        (fract(c[0] + k[2]) * 6.0 - k[3]).abs(),
+    ];
+    let clamped = [p[0] - k[0], p[1] - k[0], p[2] - k[0]];
+    [
+        c[2] * mix(k[0], clamped[0], c[1]),
+        c[2] * mix(k[0], clamped[1], c[1]),
+        c[2] * mix(k[0], clamped[2], c[1]),
+    ]
+}
+
+fn smoothstep(s: f32) -> f32 {
+    let s = s.clamp(0.0, 1.0);
+    s * s * (3.0 - 2.0 * s)
+}
+
+fn palette(properties: &Uniforms, t: f32) -> [f32; 3] {
+    let t = fract(t);
+    let n = properties.palette_stops as i32;
+    let texel = |k: i32| properties.palette[k as usize];
+
+    // the last stop at or before t
+    let mut k = -1;
+    let mut low = 0;
+    let mut high = n - 1;
+    while low <= high {
+        let middle = (low + high) / 2;
+        if texel(middle)[3] <= t {
+            k = middle;
+            low = middle + 1;
+        }
+        else {
+            high = middle - 1;
+        }
+    }
+
+    let mut a = texel((k + n) % n);
+    let mut b = texel((k + 1) % n);
+    if k < 0 {
+        a[3] -= 1.0;
+    }
+    if k == n - 1 {
+        b[3] += 1.0;
+    }
+
+    let mut s = 0.0;
+    if b[3] > a[3] {
+        s = (t - a[3]) / (b[3] - a[3]);
+    }
+    if properties.palette_interpolation == INTERPOLATION_SMOOTH {
+        s = smoothstep(s);
+    }
+    else if properties.palette_interpolation == INTERPOLATION_STEP {
+        s = 0.0;
+    }
+    srgb_to_linear([0, 1, 2].map(|i| mix(a[i], b[i], s)))
+}
+
+fn palette_colour(properties: &Uniforms, iter: f32) -> [f32; 3] {
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    palette(properties, offset + properties.palette_scale * iter / properties.max_iter as f32)
+}
+
+struct Trap {
+    distance: f32,
+    hit: u32,
+    hit_distance: f32,
+}
+
+fn new_trap() -> Trap {
+    Trap { distance: 1e30, hit: 0, hit_distance: 0.0 }
+}
+
+fn trap_colouring(properties: &Uniforms) -> bool {
+    properties.colouring == COLOURING_TRAP_DISTANCE || properties.colouring == COLOURING_TRAP_ITERATION
+}
+
+fn trap_distance(properties: &Uniforms, z: Complex32) -> f32 {
+    let d = [z[0] - properties.trap_center[0], z[1] - properties.trap_center[1]];
+    let direction = [properties.trap_angle.cos(), properties.trap_angle.sin()];
+    let along = (d[0] * direction[0] + d[1] * direction[1]).abs();
+    let across = (d[0] * direction[1] - d[1] * direction[0]).abs();
+    if properties.trap == TRAP_LINE {
+        across
+    }
+    else if properties.trap == TRAP_CROSS {
+        across.min(along)
+    }
+    else if properties.trap == TRAP_CIRCLE {
+        (length32(d) - properties.trap_radius).abs()
+    }
+    else if properties.trap == TRAP_STALK {
+        d[0].abs().min(d[1].abs())
+    }
+    else {
+        length32(d)
+    }
+}
+
+fn update_trap(properties: &Uniforms, trap: &mut Trap, z: Complex32, n: u32) {
+    if !trap_colouring(properties) {
+        return;
+    }
+    let distance = trap_distance(properties, z);
+    trap.distance = trap.distance.min(distance);
+    if trap.hit == 0 && distance < properties.trap_width {
+        trap.hit = n;
+        trap.hit_distance = distance;
+    }
+}
+
+fn inside_colour(properties: &Uniforms, trap: &Trap) -> [f32; 3] {
+    if properties.colouring == COLOURING_TRAP_DISTANCE {
+        return trap_colour(properties, trap);
+    }
+    [0.0; 3]
+}
+
+fn trap_colour(properties: &Uniforms, trap: &Trap) -> [f32; 3] {
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    if properties.colouring == COLOURING_TRAP_DISTANCE {
+        return palette(properties, offset + properties.palette_scale * (trap.distance / properties.trap_width).min(1.0));
+    }
+    palette_colour(properties, trap.hit as f32 + trap.hit_distance / properties.trap_width)
+}
+
+struct Average {
+    sum: f32,
+    last: f32,
+    count: u32,
+    z1: Complex32,
+    z2: Complex32,
+}
+
+fn new_average(z: Complex32) -> Average {
+    Average { sum: 0.0, last: 0.0, count: 0, z1: z, z2: z }
+}
+
+fn average_colouring(properties: &Uniforms) -> bool {
+    properties.colouring == COLOURING_STRIPE
+        || properties.colouring == COLOURING_TRIANGLE
+        || properties.colouring == COLOURING_CURVATURE
+}
+
+fn update_average(properties: &Uniforms, average: &mut Average, z: Complex32, c: Complex32) {
+    if !average_colouring(properties) {
+        return;
+    }
+    let term;
+    let valid;
+    if properties.colouring == COLOURING_STRIPE {
+        term = 0.5 + 0.5 * (properties.stripe_density * z[1].atan2(z[0])).sin();
+        valid = true;
+    }
+    else if properties.colouring == COLOURING_TRIANGLE {
+        let power = length32(average.z1).powf(properties.exponent);
+        let lowest = (power - length32(c)).abs();
+        let highest = power + length32(c);
+        valid = highest > lowest;
+        term = (length32(z) - lowest) / (highest - lowest);
+    }
+    else {
+        let a = sub32(z, average.z1);
+        let b = sub32(average.z1, average.z2);
+        let cross = b[0] * a[1] - b[1] * a[0];
+        let along = a[0] * b[0] + a[1] * b[1];
+        valid = cross != 0.0 || along != 0.0;
+        term = cross.atan2(along).abs() / std::f32::consts::PI;
+    }
+    average.z2 = average.z1;
+    average.z1 = z;
+    if valid {
+        average.sum += term;
+        average.last = term;
+        average.count += 1;
+    }
+}
+
+fn average_colour(properties: &Uniforms, average: &Average, abs: f32, n: u32) -> [f32; 3] {
+    let mut mean = average.sum;
+    if average.count > 1 {
+        let previous = (average.sum - average.last) / (average.count - 1) as f32;
+        let t = (smooth_iter(properties, abs, n) - n as f32).clamp(0.0, 1.0);
+        mean = mix(previous, average.sum / average.count as f32, t);
+    }
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    palette(properties, offset + properties.palette_scale * mean)
+}
+
+fn smooth_iter(properties: &Uniforms, abs: f32, n: u32) -> f32 {
+    n as f32 + 1.0 - (abs.ln() / properties.bailout.ln()).ln() / properties.exponent.ln()
+}
+
+fn distance_estimate(abs: f32, der: f32) -> f32 {
+    abs * abs.ln() / der
+}
+
+fn colour(properties: &Uniforms, abs: f32, n: u32, distance: f32, trap: &Trap) -> [f32; 3] {
+    if properties.colouring == COLOURING_TRAP_DISTANCE
+        || (properties.colouring == COLOURING_TRAP_ITERATION && trap.hit != 0)
+    {
+        trap_colour(properties, trap)
+    }
+    else if properties.colouring == COLOURING_DISTANCE {
+        let pixels = distance * 0.5 * properties.width.min(properties.height);
+        srgb_to_linear([pixels.sqrt().clamp(0.0, 1.0); 3])
+    }
+    else if properties.colouring == COLOURING_SMOOTH {
+        palette_colour(properties, smooth_iter(properties, abs, n))
+    }
+    else if properties.colouring == COLOURING_HISTOGRAM {
+        [smooth_iter(properties, abs, n).max(0.0), 1.0, 0.0]
+    }
+    else if properties.colouring == COLOURING_TRAP_ITERATION || average_colouring(properties) {
+        palette_colour(properties, smooth_iter(properties, abs, n))
+    }
+    else {
+        palette_colour(properties, n as f32)
+    }
+}
+
+const AMBIENT: f32 = 0.2;
+const SHININESS: f32 = 20.0;
+
+fn normalize3(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    v.map(|x| x / length)
+}
+
+fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn light(properties: &Uniforms, rgb: [f32; 3], z: Complex32, der: Complex32) -> [f32; 3] {
+    if properties.lighting == 0 || properties.colouring == COLOURING_HISTOGRAM {
+        return rgb;
+    }
+    let a = z.map(|x| x / length32(z));
+    let b = der.map(|x| x / length32(der));
+    let u = [a[0] * b[0] + a[1] * b[1], a[1] * b[0] - a[0] * b[1]];
+    let normal = normalize3([u[0], u[1], 1.0]);
+    let to_light = normalize3([properties.light_angle.cos(), properties.light_angle.sin(), properties.light_height]);
+    let diffuse = dot3(normal, to_light).max(0.0);
+    let halfway = normalize3([to_light[0], to_light[1], to_light[2] + 1.0]);
+    let specular = properties.specular * dot3(normal, halfway).max(0.0).powf(SHININESS);
+    rgb.map(|x| x * (AMBIENT + (1.0 - AMBIENT) * diffuse) + specular)
+}
+
+/// The histogram buffer after the compute passes of `histogram.wgsl`.
+struct Histogram {
+    min_inverted: u32,
+    max: u32,
+    counts: Vec<u32>,
+}
+
+fn load_iter(texel: [f32; 3]) -> f32 {
+    if texel[1] == 0.0 {
+        return -1.0;
+    }
+    texel[0] / texel[1]
+}
+
+/// The passes `range`, `count` and `accumulate` over the iteration texture.
+fn compute_histogram(iterations: &[[f32; 3]]) -> Histogram {
+    let mut histogram = Histogram { min_inverted: 0, max: 0, counts: vec![0; HISTOGRAM_BINS] };
+    for &texel in iterations {
+        let iter = load_iter(texel);
+        if iter >= 0.0 {
+            let bits = iter.to_bits();
+            histogram.min_inverted = histogram.min_inverted.max(!bits);
+            histogram.max = histogram.max.max(bits);
+        }
+    }
+
+    let lowest = f32::from_bits(!histogram.min_inverted);
+    let highest = f32::from_bits(histogram.max);
+    for &texel in iterations {
+        let iter = load_iter(texel);
+        if iter >= 0.0 {
+            let bin = (iter - lowest) / (highest - lowest).max(1e-6) * HISTOGRAM_BINS as f32;
+            histogram.counts[(bin as usize).min(HISTOGRAM_BINS - 1)] += 1;
+        }
+    }
+
+    let mut total = 0;
+    for count in &mut histogram.counts {
+        total += *count;
+        *count = total;
+    }
+    histogram
+}
+
+fn histogram_cdf(histogram: &Histogram, iter: f32) -> f32 {
+    let lowest = f32::from_bits(!histogram.min_inverted);
+    let highest = f32::from_bits(histogram.max);
+    let bin = (iter - lowest) / (highest - lowest).max(1e-6) * HISTOGRAM_BINS as f32;
+    let k = (bin.max(0.0) as usize).min(HISTOGRAM_BINS - 1);
+
+    let below = if k > 0 { histogram.counts[k - 1] } else { 0 };
+    let within = (histogram.counts[k] - below) as f32 * (bin - k as f32).clamp(0.0, 1.0);
+    (below as f32 + within) / histogram.counts[HISTOGRAM_BINS - 1].max(1) as f32
+}
+
+fn fs_histogram(properties: &Uniforms, histogram: &Histogram, texel: [f32; 3]) -> [f32; 3] {
+    if texel[1] == 0.0 {
+        return [0.0; 3];
+    }
+
+    let t = histogram_cdf(histogram, texel[0] / texel[1]);
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    palette(properties, offset + properties.palette_scale * t).map(|c| c * texel[1])
+}
+
+fn check_cardioid_or_bulb(properties: &Uniforms) -> bool {
+    properties.interior_checks != 0 && properties.fold == 0 && properties.exponent == 2.0
+        && properties.custom == 0 && !trap_colouring(properties)
+}
+
+fn in_cardioid_or_bulb32(c: Complex32) -> bool {
+    let x = c[0] - 0.25;
+    let y2 = c[1] * c[1];
+    let q = x * x + y2;
+    let x1 = c[0] + 1.0;
+    q * (q + x) <= 0.25 * y2 || x1 * x1 + y2 <= 0.0625
+}
+
+fn in_cardioid_or_bulb64(c: Complex64) -> bool {
+    let x = c[0] - 0.25;
+    let y2 = c[1] * c[1];
+    let q = x * x + y2;
+    let x1 = c[0] + 1.0;
+    q * (q + x) <= 0.25 * y2 || x1 * x1 + y2 <= 0.0625
+}
+
+fn in_cardioid_or_bulbds(c: ComplexDs) -> bool {
+    let x = sub_ds(c.x, [0.25, 0.0]);
+    let y2 = mul_ds(c.y, c.y);
+    let q = add_ds(mul_ds(x, x), y2);
+    let cardioid = sub_ds(mul_ds(q, add_ds(q, x)), [0.25 * y2[0], 0.25 * y2[1]]);
+    let x1 = add_ds(c.x, [1.0, 0.0]);
+    let bulb = sub_ds(add_ds(mul_ds(x1, x1), y2), [0.0625, 0.0]);
+    cardioid[0] <= 0.0 || bulb[0] <= 0.0
+}
+
+fn in_cardioid_or_bulbdd(c: ComplexDd) -> bool {
+    let x = sub_dd(c.x, [0.25, 0.0]);
+    let y2 = mul_dd(c.y, c.y);
+    let q = add_dd(mul_dd(x, x), y2);
+    let cardioid = sub_dd(mul_dd(q, add_dd(q, x)), [0.25 * y2[0], 0.25 * y2[1]]);
+    let x1 = add_dd(c.x, [1.0, 0.0]);
+    let bulb = sub_dd(add_dd(mul_dd(x1, x1), y2), [0.0625, 0.0]);
+    cardioid[0] <= 0.0 || bulb[0] <= 0.0
+}
+
+const PERIODICITY_EPSILON: f64 = 1e-4;
+
+fn julia32(properties: &Uniforms, c: Complex32, z: Complex32, dz: Complex32, dc: f32) -> [f32; 3] {
+    let mut z = z;
+    let mut der = dz;
+    let mut n = 0;
+    let mut abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    let epsilon = properties.zoom as f32 * PERIODICITY_EPSILON as f32;
+    let mut saved = z;
+    let mut period = 0;
+    let mut period_limit = 1;
+    let mut trap = new_trap();
+    let mut average = new_average(z);
+
+    while abs < bailout && n < properties.max_iter {
+        if properties.custom != 0 {
+            custom_step32(properties, &mut z, &mut der, c, [dc, 0.0]);
+        }
+        else {
+            step32(properties, &mut z, &mut der);
+            der[0] += dc;
+            z[0] += c[0];
+            z[1] += c[1];
+        }
+        abs = z[0] * z[0] + z[1] * z[1];
+        n += 1;
+        update_trap(properties, &mut trap, z, n);
+        update_average(properties, &mut average, z, c);
+
+        if properties.interior_checks != 0 {
+            let d = [z[0] - saved[0], z[1] - saved[1]];
+            if d[0] * d[0] + d[1] * d[1] < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period += 1;
+            if period == period_limit {
+                saved = z;
+                period = 0;
+                period_limit *= 2;
+            }
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        light(properties, average_colour(properties, &average, abs, n), z, der)
+    }
+    else {
+        light(properties, colour(properties, abs, n, distance_estimate(abs, length32(der)), &trap), z, der)
+    }
+}
+
+fn julia64(properties: &Uniforms, c: Complex64, z: Complex64, dz: Complex64, dc: f64) -> [f32; 3] {
+    let mut z = z;
+    let mut der = dz;
+    let mut n = 0;
+    let mut abs = 0.0;
+    let bailout = (properties.bailout * properties.bailout) as f64;
+    let epsilon = properties.zoom * PERIODICITY_EPSILON;
+    let mut saved = z;
+    let mut period = 0;
+    let mut period_limit = 1;
+    let mut trap = new_trap();
+    let mut average = new_average(z.map(|x| x as f32));
+
+    while abs < bailout && n < properties.max_iter {
+        step64(properties, &mut z, &mut der);
+        der[0] += dc;
+        z[0] += c[0];
+        z[1] += c[1];
+        abs = z[0] * z[0] + z[1] * z[1];
+        n += 1;
+        update_trap(properties, &mut trap, z.map(|x| x as f32), n);
+        update_average(properties, &mut average, z.map(|x| x as f32), c.map(|x| x as f32));
+
+        if properties.interior_checks != 0 {
+            let d = [z[0] - saved[0], z[1] - saved[1]];
+            if d[0] * d[0] + d[1] * d[1] < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period += 1;
+            if period == period_limit {
+                saved = z;
+                period = 0;
+                period_limit *= 2;
+            }
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        light(properties, average_colour(properties, &average, abs as f32, n), z.map(|x| x as f32), der.map(|x| (x / length64(der)) as f32))
+    }
+    else {
+        light(properties, colour(properties, abs as f32, n, distance_estimate(abs as f32, length64(der) as f32), &trap),
+            z.map(|x| x as f32), der.map(|x| (x / length64(der)) as f32))
+    }
+}
+
+fn series32(u: Complex32, zoom: f32, series: &[Complex32]) -> Complex32 {
+    let mut sum = series[series.len() - 1];
+    for k in (0..series.len() - 1).rev() {
+        let product = mul32(sum, u);
+        sum = [product[0] + series[k][0], product[1] + series[k][1]];
+    }
+    let product = mul32(sum, u);
+    [product[0] * zoom, product[1] * zoom]
+}
+
+fn series32_derivative(u: Complex32, zoom: f32, series: &[Complex32]) -> Complex32 {
+    let mut sum = series[series.len() - 1];
+    let mut der = [0.0; 2];
+    for k in (0..series.len() - 1).rev() {
+        let product = mul32(der, u);
+        der = [product[0] + sum[0], product[1] + sum[1]];
+        let product = mul32(sum, u);
+        sum = [product[0] + series[k][0], product[1] + series[k][1]];
+    }
+    let product = mul32(der, u);
+    [(product[0] + sum[0]) * zoom, (product[1] + sum[1]) * zoom]
+}
+
+fn power_difference32(properties: &Uniforms, reference: Complex32, dz: Complex32) -> Complex32 {
+    let z = [reference[0] + dz[0], reference[1] + dz[1]];
+    let mut sum = [1.0, 0.0];
+    let mut power = [1.0, 0.0];
+    for _ in 1..properties.exponent as u32 {
+        power = mul32(power, reference);
+        let product = mul32(sum, z);
+        sum = [product[0] + power[0], product[1] + power[1]];
+    }
+    mul32(sum, dz)
+}
+
+fn diffabs32(a: f32, d: f32) -> f32 {
+    if a >= 0.0 {
+        if a + d >= 0.0 { return d; }
+        return -(2.0 * a + d);
+    }
+    if a + d > 0.0 { return 2.0 * a + d; }
+    -d
+}
+
+fn fold_difference32(properties: &Uniforms, reference: Complex32, dz: Complex32) -> Complex32 {
+    let mut d = dz;
+    if properties.fold & FOLD_ABS_X != 0 { d[0] = diffabs32(reference[0], dz[0]); }
+    if properties.fold & FOLD_ABS_Y != 0 { d[1] = diffabs32(reference[1], dz[1]); }
+    if properties.fold & FOLD_CONJUGATE != 0 { d[1] = -d[1]; }
+    d
+}
+
+fn perturbation_step32(properties: &Uniforms, reference: Complex32, dz: &mut Complex32, der: &mut Complex32) {
+    let w = fold32(properties, reference);
+    let dw = fold_difference32(properties, reference, *dz);
+    let p = pow32([w[0] + dw[0], w[1] + dw[1]], properties.exponent - 1.0);
+    let z = [reference[0] + dz[0], reference[1] + dz[1]];
+    *der = mul32(p, fold_derivative32(properties, z, *der)).map(|d| properties.exponent * d);
+    *dz = power_difference32(properties, w, dw);
+    if properties.fold & FOLD_ABS_RE != 0 {
+        let a = pow32(w, properties.exponent)[0];
+        if a + dz[0] < 0.0 { der[0] = -der[0]; }
+        dz[0] = diffabs32(a, dz[0]);
+    }
+}
+
+fn perturbation32(properties: &Uniforms, u: Complex32, center: Complex32, zoom: f32, orbit: &Orbit32) -> [f32; 3] {
+    let dc = [u[0] * zoom, u[1] * zoom];
+    let c = [center[0] + dc[0], center[1] + dc[1]];
+    let mut dz = series32(u, zoom, &orbit.series);
+    let mut der = series32_derivative(u, zoom, &orbit.series);
+    let mut m = orbit.skip as usize;
+    let mut n = orbit.skip;
+    let orbit = &orbit.points;
+    let mut abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    let mut trap = new_trap();
+    let mut average = new_average(orbit[0]);
+    for &z in &orbit[1..=m] {
+        update_average(properties, &mut average, z, c);
+    }
+
+    while abs < bailout && n < properties.max_iter {
+        perturbation_step32(properties, orbit[m], &mut dz, &mut der);
+        der[0] += zoom;
+        dz = [dz[0] + dc[0], dz[1] + dc[1]];
+        m += 1;
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        abs = z[0] * z[0] + z[1] * z[1];
+        n += 1;
+        update_trap(properties, &mut trap, z, n);
+        update_average(properties, &mut average, z, c);
+
+        if abs < dz[0] * dz[0] + dz[1] * dz[1] || m == orbit.len() - 1 {
+            dz = z;
+            m = 0;
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        light(properties, average_colour(properties, &average, abs, n), z, der)
+    }
+    else {
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        light(properties, colour(properties, abs, n, distance_estimate(abs, length32(der)), &trap), z, der)
+    }
+}
+
+fn series64(u: Complex64, zoom: f64, series: &[Complex64]) -> Complex64 {
+    let mut sum = series[series.len() - 1];
+    for k in (0..series.len() - 1).rev() {
+        let product = mul64(sum, u);
+        sum = [product[0] + series[k][0], product[1] + series[k][1]];
+    }
+    let product = mul64(sum, u);
+    [product[0] * zoom, product[1] * zoom]
+}
+
+fn series64_derivative(u: Complex64, zoom: f64, series: &[Complex64]) -> Complex64 {
+    let mut sum = series[series.len() - 1];
+    let mut der = [0.0; 2];
+    for k in (0..series.len() - 1).rev() {
+        let product = mul64(der, u);
+        der = [product[0] + sum[0], product[1] + sum[1]];
+        let product = mul64(sum, u);
+        sum = [product[0] + series[k][0], product[1] + series[k][1]];
+    }
+    let product = mul64(der, u);
+    [(product[0] + sum[0]) * zoom, (product[1] + sum[1]) * zoom]
+}
+
+fn power_difference64(properties: &Uniforms, reference: Complex64, dz: Complex64) -> Complex64 {
+    let z = [reference[0] + dz[0], reference[1] + dz[1]];
+    let mut sum = [1.0, 0.0];
+    let mut power = [1.0, 0.0];
+    for _ in 1..properties.exponent as u32 {
+        power = mul64(power, reference);
+        let product = mul64(sum, z);
+        sum = [product[0] + power[0], product[1] + power[1]];
+    }
+    mul64(sum, dz)
+}
+
+fn diffabs64(a: f64, d: f64) -> f64 {
+    if a >= 0.0 {
+        if a + d >= 0.0 { return d; }
+        return -(2.0 * a + d);
+    }
+    if a + d > 0.0 { return 2.0 * a + d; }
+    -d
+}
+
+fn fold_difference64(properties: &Uniforms, reference: Complex64, dz: Complex64) -> Complex64 {
+    let mut d = dz;
+    if properties.fold & FOLD_ABS_X != 0 { d[0] = diffabs64(reference[0], dz[0]); }
+    if properties.fold & FOLD_ABS_Y != 0 { d[1] = diffabs64(reference[1], dz[1]); }
+    if properties.fold & FOLD_CONJUGATE != 0 { d[1] = -d[1]; }
+    d
+}
+
+fn perturbation_step64(properties: &Uniforms, reference: Complex64, dz: &mut Complex64, der: &mut Complex64) {
+    let w = fold64(properties, reference);
+    let dw = fold_difference64(properties, reference, *dz);
+    let p = pow64([w[0] + dw[0], w[1] + dw[1]], properties.exponent as u32 - 1);
+    let z = [reference[0] + dz[0], reference[1] + dz[1]];
+    *der = mul64(p, fold_derivative64(properties, z, *der)).map(|d| properties.exponent as f64 * d);
+    *dz = power_difference64(properties, w, dw);
+    if properties.fold & FOLD_ABS_RE != 0 {
+        let a = pow64(w, properties.exponent as u32)[0];
+        if a + dz[0] < 0.0 { der[0] = -der[0]; }
+        dz[0] = diffabs64(a, dz[0]);
+    }
+}
+
+fn perturbation64(properties: &Uniforms, u: Complex64, center: Complex64, zoom: f64, orbit: &Orbit64) -> [f32; 3] {
+    let dc = [u[0] * zoom, u[1] * zoom];
+    let c = [(center[0] + dc[0]) as f32, (center[1] + dc[1]) as f32];
+    let mut dz = series64(u, zoom, &orbit.series);
+    let mut der = series64_derivative(u, zoom, &orbit.series);
+    let mut m = orbit.skip as usize;
+    let mut n = orbit.skip;
+    let orbit = &orbit.points;
+    let mut abs = 0.0;
+    let bailout = (properties.bailout * properties.bailout) as f64;
+    let mut trap = new_trap();
+    let mut average = new_average(orbit[0].map(|x| x as f32));
+    for z in &orbit[1..=m] {
+        update_average(properties, &mut average, z.map(|x| x as f32), c);
+    }
+
+    while abs < bailout && n < properties.max_iter {
+        perturbation_step64(properties, orbit[m], &mut dz, &mut der);
+        der[0] += zoom;
+        dz = [dz[0] + dc[0], dz[1] + dc[1]];
+        m += 1;
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        abs = z[0] * z[0] + z[1] * z[1];
+        n += 1;
+        update_trap(properties, &mut trap, z.map(|x| x as f32), n);
+        update_average(properties, &mut average, z.map(|x| x as f32), c);
+
+        if abs < dz[0] * dz[0] + dz[1] * dz[1] || m == orbit.len() - 1 {
+            dz = z;
+            m = 0;
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        light(properties, average_colour(properties, &average, abs as f32, n), z.map(|x| x as f32), der.map(|x| (x / length64(der)) as f32))
+    }
+    else {
+        let z = [orbit[m][0] + dz[0], orbit[m][1] + dz[1]];
+        light(properties, colour(properties, abs as f32, n, distance_estimate(abs as f32, length64(der) as f32), &trap),
+            z.map(|x| x as f32), der.map(|x| (x / length64(der)) as f32))
+    }
+}
+
+fn juliads(properties: &Uniforms, c: ComplexDs, z: ComplexDs, dz: Complex32, dc: f32) -> [f32; 3] {
+    let mut z = z;
+    let mut der = dz;
+    let mut n = 0;
+    let mut abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    let epsilon = properties.zoom as f32 * PERIODICITY_EPSILON as f32;
+    let mut saved = z;
+    let mut period = 0;
+    let mut period_limit = 1;
+    let mut trap = new_trap();
+    let mut average = new_average([z.x[0], z.y[0]]);
+
+    while abs < bailout && n < properties.max_iter {
+        stepds(properties, &mut z, &mut der);
+        der[0] += dc;
+        z.x = add_ds(z.x, c.x);
+        z.y = add_ds(z.y, c.y);
+        abs = z.x[0] * z.x[0] + z.y[0] * z.y[0];
+        n += 1;
+        update_trap(properties, &mut trap, [z.x[0], z.y[0]], n);
+        update_average(properties, &mut average, [z.x[0], z.y[0]], [c.x[0], c.y[0]]);
+
+        if properties.interior_checks != 0 {
+            let d = [sub_ds(z.x, saved.x)[0], sub_ds(z.y, saved.y)[0]];
+            if d[0] * d[0] + d[1] * d[1] < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period += 1;
+            if period == period_limit {
+                saved = z;
+                period = 0;
+                period_limit *= 2;
+            }
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        light(properties, average_colour(properties, &average, abs, n), [z.x[0], z.y[0]], der)
+    }
+    else {
+        light(properties, colour(properties, abs, n, distance_estimate(abs, length32(der)), &trap), [z.x[0], z.y[0]], der)
+    }
+}
+
+fn mandelbrotds(properties: &Uniforms, c: ComplexDs) -> [f32; 3] {
+    if check_cardioid_or_bulb(properties) && in_cardioid_or_bulbds(c) {
+        return [0.0; 3];
+    }
+    juliads(properties, c, ComplexDs { x: [0.0, 0.0], y: [0.0, 0.0] }, [0.0, 0.0], properties.zoom as f32)
+}
+
+fn fractalds(properties: &Uniforms, p: ComplexDs) -> [f32; 3] {
+    if properties.julia != 0 {
+        let c = ComplexDs { x: [properties.julia_c[0] as f32, 0.0], y: [properties.julia_c[1] as f32, 0.0] };
+        return juliads(properties, c, p, [properties.zoom as f32, 0.0], 0.0);
+    }
+    mandelbrotds(properties, p)
+}
+
+fn juliadd(properties: &Uniforms, c: ComplexDd, z: ComplexDd, dz: Complex64, dc: f64) -> [f32; 3] {
+    let mut z = z;
+    let mut der = dz;
+    let mut n = 0;
+    let mut abs = 0.0;
+    let bailout = (properties.bailout * properties.bailout) as f64;
+    let epsilon = properties.zoom * PERIODICITY_EPSILON;
+    let mut saved = z;
+    let mut period = 0;
+    let mut period_limit = 1;
+    let mut trap = new_trap();
+    let mut average = new_average([z.x[0] as f32, z.y[0] as f32]);
+
+    while abs < bailout && n < properties.max_iter {
+        stepdd(properties, &mut z, &mut der);
+        der[0] += dc;
+        z.x = add_dd(z.x, c.x);
+        z.y = add_dd(z.y, c.y);
+        abs = z.x[0] * z.x[0] + z.y[0] * z.y[0];
+        n += 1;
+        update_trap(properties, &mut trap, [z.x[0] as f32, z.y[0] as f32], n);
+        update_average(properties, &mut average, [z.x[0] as f32, z.y[0] as f32], [c.x[0] as f32, c.y[0] as f32]);
+
+        if properties.interior_checks != 0 {
+            let d = [sub_dd(z.x, saved.x)[0], sub_dd(z.y, saved.y)[0]];
+            if d[0] * d[0] + d[1] * d[1] < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period += 1;
+            if period == period_limit {
+                saved = z;
+                period = 0;
+                period_limit *= 2;
+            }
+        }
+    }
+
+    let abs = abs.sqrt();
+
+    if n == properties.max_iter {
+        inside_colour(properties, &trap)
+    }
+    else if average_colouring(properties) {
+        light(properties, average_colour(properties, &average, abs as f32, n), [z.x[0] as f32, z.y[0] as f32],
+            der.map(|x| (x / length64(der)) as f32))
+    }
+    else {
+        light(properties, colour(properties, abs as f32, n, distance_estimate(abs as f32, length64(der) as f32), &trap),
+            [z.x[0] as f32, z.y[0] as f32], der.map(|x| (x / length64(der)) as f32))
+    }
+}
+
+fn mandelbrotdd(properties: &Uniforms, c: ComplexDd) -> [f32; 3] {
+    if check_cardioid_or_bulb(properties) && in_cardioid_or_bulbdd(c) {
+        return [0.0; 3];
+    }
+    juliadd(properties, c, ComplexDd { x: [0.0, 0.0], y: [0.0, 0.0] }, [0.0, 0.0], properties.zoom)
+}
+
+fn fractaldd(properties: &Uniforms, p: ComplexDd) -> [f32; 3] {
+    if properties.julia != 0 {
+        let c = ComplexDd { x: [properties.julia_c[0], 0.0], y: [properties.julia_c[1], 0.0] };
+        return juliadd(properties, c, p, [properties.zoom, 0.0], 0.0);
+    }
+    mandelbrotdd(properties, p)
+}
+
+fn mandelbrot32(properties: &Uniforms, c: Complex32) -> [f32; 3] {
+    if check_cardioid_or_bulb(properties) && in_cardioid_or_bulb32(c) {
+        return [0.0; 3];
+    }
+    if properties.custom != 0 {
+        let dc = properties.zoom as f32;
+        return julia32(properties, c, c, [dc, 0.0], dc);
+    }
+    julia32(properties, c, [0.0, 0.0], [0.0, 0.0], properties.zoom as f32)
+}
+
+const NEWTON_EPSILON: f32 = 1e-10;
+
+fn newton_colour(properties: &Uniforms, root: u32, n: u32, distance: f32) -> [f32; 3] {
+    let mut iter = n as f32;
+    if properties.colouring != COLOURING_ITERATIONS {
+        let t = (distance.ln() / NEWTON_EPSILON.ln()).clamp(1.0, 2.0);
+        iter += 1.0 - t.log2();
+    }
+    let hue = root as f32 / properties.polynomial.degree as f32;
+    srgb_to_linear(hsv2rgb([hue, 0.75, (-0.1 * iter).exp()]))
+}
+
+fn newton32(properties: &Uniforms, z: Complex32) -> [f32; 3] {
+    let polynomial = &properties.polynomial;
+    let degree = polynomial.degree as usize;
+    let mut z = z;
+    for n in 0..properties.max_iter {
+        let mut p = polynomial.coefficients[degree];
+        let mut dp = [0.0, 0.0];
+        for k in (0..degree).rev() {
+            let dz = mul32(dp, z);
+            dp = [dz[0] + p[0], dz[1] + p[1]];
+            let pz = mul32(p, z);
+            p = [pz[0] + polynomial.coefficients[k][0], pz[1] + polynomial.coefficients[k][1]];
+        }
+        let step = div32(p, dp);
+        z = [z[0] - step[0], z[1] - step[1]];
+
+        for (r, root) in polynomial.roots[..degree].iter().enumerate() {
+            let d = [z[0] - root[0], z[1] - root[1]];
+            let distance = d[0] * d[0] + d[1] * d[1];
+            if distance < NEWTON_EPSILON {
+                return newton_colour(properties, r as u32, n, distance);
+            }
+        }
+    }
+    [0.0; 3]
+}
+
+fn fractal32(properties: &Uniforms, p: Complex32) -> [f32; 3] {
+    if properties.newton != 0 {
+        return newton32(properties, p);
+    }
+    if properties.julia != 0 {
+        let c = [properties.julia_c[0] as f32, properties.julia_c[1] as f32];
+        return julia32(properties, c, p, [properties.zoom as f32, 0.0], 0.0);
+    }
+    mandelbrot32(properties, p)
+}
+
+fn mandelbrot64(properties: &Uniforms, c: Complex64) -> [f32; 3] {
+    if check_cardioid_or_bulb(properties) && in_cardioid_or_bulb64(c) {
+        return [0.0; 3];
+    }
+    julia64(properties, c, [0.0, 0.0], [0.0, 0.0], properties.zoom)
+}
+
+fn fractal64(properties: &Uniforms, p: Complex64) -> [f32; 3] {
+    if properties.julia != 0 {
+        return julia64(properties, properties.julia_c, p, [properties.zoom, 0.0], 0.0);
+    }
+    mandelbrot64(properties, p)
+}
+
+/// Texture coordinates of the centre of pixel `(x, y)`, as interpolated
+/// between the vertices produced by `index_to_tex`.
+fn tex_coords(x: u32, y: u32, width: f32, height: f32) -> [f32; 2] {
+    let aspect = width / height;
+
+    let mut x_diff = 0.0;
+    let mut y_diff = 0.0;
+    if aspect > 1.0 {
+        x_diff = (aspect - 1.0) / 2.0;
+    }
+    else if aspect < 1.0 {
+        y_diff = (1.0 / aspect - 1.0) / 2.0;
+    }
+
+    let x0 = 0.0 - x_diff;
+    let x1 = 1.0 + x_diff;
+    let y0 = 0.0 - y_diff;
+    let y1 = 1.0 + y_diff;
+
+    // framebuffer rows go from the top of the window down
+    let u = (x as f32 + 0.5) / width;
+    let v = 1.0 - (y as f32 + 0.5) / height;
+
+    [x0 + u * (x1 - x0), y0 + v * (y1 - y0)]
+}
+
+fn pixel32(tex_coords: [f32; 2], center: [f32; 2], zoom: f32) -> Complex32 {
+    [
+        (tex_coords[0] * 2.0 - 1.0) * zoom + center[0],
+        (tex_coords[1] * 2.0 - 1.0) * zoom + center[1],
+    ]
+}
+
+fn colour_at_ds(properties: &Properties32, uniforms: &Uniforms, orbit: &Orbit32, tex_coords: [f32; 2]) -> [f32; 3] {
+    let u = pixel32(tex_coords, [0.0, 0.0], 1.0);
+
+    if properties.math64 == PERTURBATION {
+        perturbation32(uniforms, u, properties.center, properties.zoom, orbit)
+    }
+    else if properties.math64 != MATH32 {
+        let offset = [u[0] * properties.zoom, u[1] * properties.zoom];
+        let c = ComplexDs {
+            x: add_ds([properties.center[0], properties.center_lo[0]], [offset[0], 0.0]),
+            y: add_ds([properties.center[1], properties.center_lo[1]], [offset[1], 0.0]),
+        };
+        fractalds(uniforms, c)
+    }
+    else {
+        fractal32(uniforms, pixel32(tex_coords, properties.center, properties.zoom))
+    }
+}
+
+fn colour_at64(properties: &Properties, uniforms: &Uniforms, orbit: &Orbit64, tex_coords: [f32; 2]) -> [f32; 3] {
+    if properties.math64 == PERTURBATION {
+        let u = [(tex_coords[0] * 2.0 - 1.0) as f64, (tex_coords[1] * 2.0 - 1.0) as f64];
+        perturbation64(uniforms, u, properties.center, properties.zoom, orbit)
+    }
+    else if properties.math64 == DOUBLE_DOUBLE {
+        let offset = [
+            (tex_coords[0] * 2.0 - 1.0) as f64 * properties.zoom,
+            (tex_coords[1] * 2.0 - 1.0) as f64 * properties.zoom,
+        ];
+        let c = ComplexDd {
+            x: add_dd([properties.center[0], properties.center_lo[0]], [offset[0], 0.0]),
+            y: add_dd([properties.center[1], properties.center_lo[1]], [offset[1], 0.0]),
+        };
+        fractaldd(uniforms, c)
+    }
+    else if properties.math64 != 0 {
+        let c = [
+            (tex_coords[0] * 2.0 - 1.0) as f64 * properties.zoom + properties.center[0],
+            (tex_coords[1] * 2.0 - 1.0) as f64 * properties.zoom + properties.center[1],
+        ];
+        fractal64(uniforms, c)
+    }
+    else {
+        let center = [properties.center[0] as f32, properties.center[1] as f32];
+        fractal32(uniforms, pixel32(tex_coords, center, properties.zoom as f32))
+    }
+}
+
+fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+
+fn random(seed: u32) -> f32 {
+    (hash(seed) >> 8) as f32 / 16777216.0
+}
+
+fn sample_offset(uniforms: &Uniforms, position: [f32; 2], i: u32, side: u32) -> [f32; 2] {
+    let mut cell = [(i % side) as f32 + 0.5, (i / side) as f32 + 0.5];
+    if uniforms.sampling == SAMPLING_JITTERED {
+        let pixel = position.map(|x| x as u32);
+        let seed = hash(pixel[0] ^ hash(pixel[1] ^ hash(i ^ hash(uniforms.seed))));
+        cell = [(i % side) as f32 + random(seed), (i / side) as f32 + random(hash(seed))];
+    }
+    let pixel_size = 1.0 / uniforms.width.min(uniforms.height);
+    cell.map(|x| (x / side as f32 - 0.5) * pixel_size)
+}
+
+fn supersample<F>(uniforms: &Uniforms, position: [f32; 2], tex_coords: [f32; 2], colour_at: &F) -> [f32; 3]
+    where F: Fn([f32; 2]) -> [f32; 3]
+{
+    let side = (uniforms.samples as f32).sqrt().round() as u32;
+    let mut sum = [0.0; 3];
+    for i in 0..side * side {
+        let offset = sample_offset(uniforms, position, i, side);
+        let sample = colour_at([tex_coords[0] + offset[0], tex_coords[1] + offset[1]]);
+        for (sum, sample) in sum.iter_mut().zip(sample) {
+            *sum += sample;
+        }
+    }
+    sum.map(|x| x / (side * side) as f32)
+}
+
+fn histogram_colouring(uniforms: &Uniforms) -> bool {
+    uniforms.colouring == COLOURING_HISTOGRAM && uniforms.newton == 0
+}
+
+fn adaptive_sampling(uniforms: &Uniforms) -> bool {
+    uniforms.sampling == SAMPLING_ADAPTIVE && !histogram_colouring(uniforms)
+}
+
+fn fs_main<F>(uniforms: &Uniforms, position: [f32; 2], tex_coords: [f32; 2], colour_at: &F) -> [f32; 3]
+    where F: Fn([f32; 2]) -> [f32; 3]
+{
+    if adaptive_sampling(uniforms) {
+        return colour_at(tex_coords);
+    }
+    supersample(uniforms, position, tex_coords, colour_at)
+}
+
+const ADAPTIVE_THRESHOLD: f32 = 0.1;
+
+/// `fs_adaptive` for pixel `(x, y)` of the `samples` of `fs_main`.
+fn fs_adaptive<F>(uniforms: &Uniforms, samples: &[[f32; 3]], width: u32, height: u32, x: u32, y: u32, colour_at: &F) -> [f32; 3]
+    where F: Fn([f32; 2]) -> [f32; 3]
+{
+    let texel = samples[(y * width + x) as usize];
+
+    let mut difference: f32 = 0.0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let nx = (x as i32 + dx).clamp(0, width as i32 - 1) as u32;
+            let ny = (y as i32 + dy).clamp(0, height as i32 - 1) as u32;
+            let neighbour = samples[(ny * width + nx) as usize];
+            for (a, b) in neighbour.iter().zip(texel) {
+                difference = difference.max((a - b).abs());
+            }
+        }
+    }
+
+    if difference > ADAPTIVE_THRESHOLD {
+        let position = [x as f32 + 0.5, y as f32 + 0.5];
+        return supersample(uniforms, position, tex_coords(x, y, width as f32, height as f32), colour_at);
+    }
+    texel
+}
+
+/// Converts a colour to what an `Rgba8Unorm` render target would store.
+fn to_rgba8(colour: [f32; 3]) -> [u8; 4] {
+    let [r, g, b] = colour.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    [r, g, b, 255]
+}
+
+/// Runs `fragment` for every pixel `(x, y)` of a `width`x`height` grid,
+/// splitting the rows between all available cores.
+fn for_each_pixel<F>(width: u32, height: u32, fragment: F) -> Vec<[f32; 3]>
+    where F: Fn(u32, u32) -> [f32; 3] + Sync
+{
+    let mut pixels = vec![[0.0; 3]; width as usize * height as usize];
+    if pixels.is_empty() {
+        return pixels;
+    }
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = (height as usize).div_ceil(threads);
+    let row_len = width as usize;
+
+    thread::scope(|scope| {
+        for (i, rows) in pixels.chunks_mut(rows_per_thread * row_len).enumerate() {
+            let fragment = &fragment;
+            scope.spawn(move || {
+                for (j, row) in rows.chunks_mut(row_len).enumerate() {
+                    let y = (i * rows_per_thread + j) as u32;
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        *pixel = fragment(x as u32, y);
+                    }
+                }
+            });
+        }
+    });
+    pixels
+}
+
+/// Samples `colour_at` for every pixel of a `width`x`height` grid as `fs_main`
+/// and `fs_adaptive` do, colours the iteration counts it returns by their
+/// histogram with histogram colouring, and writes the linear colours out as
+/// they would be written to an `Rgba8Unorm` target.
+fn render_with<F>(uniforms: &Uniforms, width: u32, height: u32, colour_at: F) -> Vec<u8>
+    where F: Fn([f32; 2]) -> [f32; 3] + Sync
+{
+    let mut pixels = for_each_pixel(width, height, |x, y| {
+        let position = [x as f32 + 0.5, y as f32 + 0.5];
+        fs_main(uniforms, position, tex_coords(x, y, width as f32, height as f32), &colour_at)
+    });
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+
+    if adaptive_sampling(uniforms) {
+        let samples = pixels;
+        pixels = for_each_pixel(width, height, |x, y| fs_adaptive(uniforms, &samples, width, height, x, y, &colour_at));
+    }
+
+    if histogram_colouring(uniforms) {
+        let histogram = compute_histogram(&pixels);
+        for pixel in &mut pixels {
+            *pixel = fs_histogram(uniforms, &histogram, *pixel);
+        }
+    }
+
+    pixels.into_iter().map(|pixel| output(uniforms, pixel)).flat_map(to_rgba8).collect()
+}
+
+/// Renders `properties` in the colours of `palette` the way the 64-bit shader
+/// does, including the switch between 32-bit math, 64-bit math, perturbation
+/// and double-double on `properties.math64`.
+///
+/// The image has the size set with [`Properties::resize`] and is returned as
+/// row-major RGBA8, top row first.
+pub fn render(properties: &Properties, palette: &Palette) -> Vec<u8> {
+    render64(properties, &Uniforms::new(properties, palette))
+}
+
+/// Renders the Newton fractal of `polynomial` with the view of `properties`,
+/// the way both shaders do.
+pub fn render_newton(properties: &Properties, palette: &Palette, polynomial: &Polynomial) -> Vec<u8> {
+    let mut properties = *properties;
+    properties.newton = 1;
+    properties.math64 = MATH32;
+    let uniforms = Uniforms { polynomial: polynomial.polynomial32(), ..Uniforms::new(&properties, palette) };
+    render64(&properties, &uniforms)
+}
+
+/// Renders the custom formula `custom` with the view of `properties`,
+/// the way both shaders do after compiling it.
+pub fn render_custom(properties: &Properties, palette: &Palette, custom: &Expression) -> Vec<u8> {
+    let mut properties = *properties;
+    properties.custom = 1;
+    properties.exponent = custom.degree();
+    properties.math64 = MATH32;
+    let uniforms = Uniforms { custom_formula: custom.clone(), ..Uniforms::new(&properties, palette) };
+    render64(&properties, &uniforms)
+}
+
+fn render64(properties: &Properties, uniforms: &Uniforms) -> Vec<u8> {
+    let (width, height) = properties.size();
+    let orbit = if properties.math64 == PERTURBATION {
+        let orbit = ReferenceOrbit::at(properties.center, properties.center_lo, properties.zoom, properties.max_iter, properties.exponent as u32, properties.fold);
+        let series = SeriesApproximation::new(&orbit, properties.zoom, properties.extent(), SERIES_TOLERANCE64);
+        Orbit { points: orbit.points().to_vec(), series: series.coefficients().to_vec(), skip: series.skip() }
+    }
+    else {
+        Orbit { points: Vec::new(), series: Vec::new(), skip: 0 }
+    };
+    render_with(uniforms, width, height, |tex_coords| colour_at64(properties, uniforms, &orbit, tex_coords))
+}
+
+/// Renders `properties` in the colours of `palette` the way the double-single
+/// shader for adapters without 64-bit floats does.
+///
+/// The image is returned as row-major RGBA8, top row first.
+pub fn render32(properties: &Properties32, palette: &Palette) -> Vec<u8> {
+    let uniforms = &Uniforms::new32(properties, palette);
+    let (width, height) = (properties.width as u32, properties.height as u32);
+    let orbit = if properties.math64 == PERTURBATION {
+        let (center, center_lo) = (properties.center.map(f64::from), properties.center_lo.map(f64::from));
+        let zoom = properties.zoom as f64;
+        let orbit = ReferenceOrbit::at(center, center_lo, zoom, properties.max_iter, properties.exponent as u32, properties.fold);
+        let series = SeriesApproximation::new(&orbit, zoom, properties.extent(), SERIES_TOLERANCE32);
+        Orbit { points: orbit.points32(), series: series.coefficients32(), skip: series.skip() }
+    }
+    else {
+        Orbit { points: Vec::new(), series: Vec::new(), skip: 0 }
+    };
+    render_with(uniforms, width, height, |tex_coords| colour_at_ds(properties, uniforms, &orbit, tex_coords))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interactive::camera_controller::{MATH64, MIN_ZOOM32};
+
+    /// A single sample per pixel keeps the renders quick.
+    fn properties(width: u32, height: u32) -> Properties {
+        let mut properties = Properties { samples: 1, ..Default::default() };
+        properties.resize(width, height);
+        properties
+    }
+
+    fn pixel(image: &[u8], width: u32, x: u32, y: u32) -> [u8; 4] {
+        let i = ((y * width + x) * 4) as usize;
+        [image[i], image[i + 1], image[i + 2], image[i + 3]]
+    }
+
+    /// Pixels with a channel that differs by more than 2 between the images.
+    fn differing(a: &[u8], b: &[u8]) -> usize {
+        assert_eq!(a.len(), b.len());
+        a.chunks(4).zip(b.chunks(4))
+            .filter(|(a, b)| a.iter().zip(*b).any(|(a, b)| a.abs_diff(*b) > 2))
+            .count()
+    }
+
+    /// Mean difference of the channels of the images.
+    fn mean_difference(a: &[u8], b: &[u8]) -> f64 {
+        a.iter().zip(b).map(|(a, b)| a.abs_diff(*b) as f64).sum::<f64>() / a.len() as f64
+    }
+
+    #[test]
+    fn cardioid_is_black() {
+        let mut properties = properties(16, 16);
+        properties.center = [-0.2, 0.0];
+        properties.zoom = 0.01;
+        assert_eq!(pixel(&render(&properties, &Palette::default()), 16, 8, 8), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn exterior_is_coloured() {
+        let mut properties = properties(16, 16);
+        properties.center = [0.5, 0.5];
+        properties.zoom = 0.01;
+        assert_ne!(pixel(&render(&properties, &Palette::default()), 16, 8, 8), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn julia_set_of_zero_is_the_unit_disk() {
+        let mut properties = properties(32, 32);
+        properties.julia = 1;
+        properties.julia_c = [0.0, 0.0];
+        properties.center = [0.0, 0.0];
+        properties.zoom = 1.5;
+        let image = render(&properties, &Palette::default());
+        assert_eq!(pixel(&image, 32, 16, 16), [0, 0, 0, 255]);
+        assert_ne!(pixel(&image, 32, 0, 0), [0, 0, 0, 255]);
+        // the disk has a radius of 16 / 1.5 pixels
+        let black = image.chunks(4).filter(|pixel| pixel[..3] == [0, 0, 0]).count() as f64;
+        let area = std::f64::consts::PI * (16.0 / 1.5f64).powi(2);
+        assert!((black - area).abs() < 0.05 * area, "{black} black pixels");
+        let image32 = render32(&Properties32::from(properties), &Palette::default());
+        assert!(differing(&image, &image32) < 32 * 32 / 100);
+    }
+
+    #[test]
+    fn renders_with_the_palette() {
+        let mut properties = properties(16, 16);
+        properties.center = [0.5, 0.5];
+        properties.zoom = 0.01;
+        let grey = Palette::builtin().into_iter().find(|palette| palette.name == "Grey").unwrap();
+        let [r, g, b, _] = pixel(&render(&properties, &grey), 16, 8, 8);
+        assert!(r == g && g == b, "{r} {g} {b} is not grey");
+    }
+
+    #[test]
+    fn histogram_spreads_the_palette() {
+        let mut properties = properties(64, 48);
+        // from black to white once
+        properties.palette_scale = 0.5;
+        let grey = Palette::builtin().into_iter().find(|palette| palette.name == "Grey").unwrap();
+        let smooth = render(&properties, &grey);
+        properties.colouring = COLOURING_HISTOGRAM;
+        let image = render(&properties, &grey);
+
+        let black = |pixel: &[u8]| pixel[..3] == [0, 0, 0];
+        let exterior: Vec<u8> = smooth.chunks(4).zip(image.chunks(4))
+            .filter(|(smooth, _)| !black(smooth))
+            .map(|(_, pixel)| pixel[0])
+            .collect();
+        // the interior stays black
+        assert!(smooth.chunks(4).zip(image.chunks(4)).all(|(smooth, pixel)| !black(smooth) || black(pixel)));
+        // and the exterior is about evenly spread over the shades of grey
+        for quarter in 0..4 {
+            let count = exterior.iter().filter(|&&grey| grey as usize * 4 / 256 == quarter).count();
+            assert!(count > exterior.len() / 5, "{count} of {} pixels in quarter {quarter}", exterior.len());
+        }
+    }
+
+    #[test]
+    fn lighting_shades_the_exterior() {
+        let mut properties = properties(64, 48);
+        let flat = render(&properties, &Palette::default());
+        properties.lighting = 1;
+        let lit = render(&properties, &Palette::default());
+
+        let black = |pixel: &[u8]| pixel[..3] == [0, 0, 0];
+        let interior = flat.chunks(4).filter(|pixel| black(pixel)).count();
+        assert!(interior > 0);
+        assert!(flat.chunks(4).zip(lit.chunks(4)).all(|(flat, lit)| !black(flat) || flat == lit));
+        // most of the exterior is lit at an angle
+        assert!(differing(&flat, &lit) > (64 * 48 - interior) / 2);
+    }
+
+    #[test]
+    fn sampling_converges_to_the_grid() {
+        let mut properties = properties(64, 48);
+        let single = render(&properties, &Palette::default());
+        properties.samples = 16;
+        let grid = render(&properties, &Palette::default());
+        let error = mean_difference(&single, &grid);
+
+        properties.sampling = SAMPLING_ADAPTIVE;
+        let adaptive = render(&properties, &Palette::default());
+        assert!(mean_difference(&adaptive, &grid) < error / 2.0);
+
+        // the frames of the refinement each jitter the samples differently
+        properties.sampling = SAMPLING_JITTERED;
+        let frames: Vec<_> = (0..16)
+            .map(|seed| render(&Properties { seed, ..properties }, &Palette::default()))
+            .collect();
+        assert!(mean_difference(&frames[0], &grid) < error);
+        let accumulated: Vec<u8> = (0..grid.len())
+            .map(|i| ((frames.iter().map(|frame| frame[i] as u32).sum::<u32>() + 8) / 16) as u8)
+            .collect();
+        assert!(mean_difference(&accumulated, &grid) < error / 2.0);
+    }
+
+    #[test]
+    fn render_and_render32_agree() {
+        let properties = properties(64, 48);
+        let image = render(&properties, &Palette::default());
+        let image32 = render32(&Properties32::from(properties), &Palette::default());
+        // the boundary of the set may fall either way
+        assert!(differing(&image, &image32) < 64 * 48 / 100);
+    }
+
+    #[test]
+    fn deep_zoom_math_agrees_with_f64() {
+        let mut properties = properties(48, 32);
+        properties.center = [0.0, 1.0];
+        properties.zoom = 1e-9;
+        properties.math64 = MATH64;
+        let image = render(&properties, &Palette::default());
+
+        for math64 in [PERTURBATION, DOUBLE_DOUBLE] {
+            properties.math64 = math64;
+            let other = render(&properties, &Palette::default());
+            // the orbits of a few pixels may part after many iterations
+            assert!(differing(&image, &other) < 48 * 32 / 100, "math64 {math64}");
+        }
+    }
+
+    #[test]
+    fn render32_agrees_at_the_smallest_zoom() {
+        let mut properties = properties(48, 32);
+        properties.center = [0.0, 1.0];
+        properties.zoom = MIN_ZOOM32;
+        properties.math64 = PERTURBATION;
+        let image = render(&properties, &Palette::default());
+        let image32 = render32(&Properties32::from(properties), &Palette::default());
+        // GPUs flush subnormal f32 to zero, the delta of a pixel must stay normal
+        assert!(((MIN_ZOOM32 / 8192.0) as f32).is_normal());
+        assert!(image.chunks(4).any(|pixel| pixel != &image[..4]));
+        assert!(differing(&image, &image32) < 48 * 32 / 100);
+    }
+
+    #[test]
+    fn deep_zoom_math_averages_the_orbit() {
+        let mut properties = properties(48, 32);
+        properties.center = [-0.743643887037151, 0.131825904205330];
+        properties.zoom = 1e-9;
+        properties.max_iter = 5000;
+        properties.colouring = COLOURING_SMOOTH;
+        properties.math64 = MATH64;
+        let smooth = render(&properties, &Palette::default());
+
+        for colouring in [COLOURING_STRIPE, COLOURING_TRIANGLE, COLOURING_CURVATURE] {
+            properties.colouring = colouring;
+            properties.math64 = MATH64;
+            let image = render(&properties, &Palette::default());
+            assert!(differing(&image, &smooth) > 48 * 32 / 2, "colouring {colouring}");
+            for math64 in [PERTURBATION, DOUBLE_DOUBLE] {
+                properties.math64 = math64;
+                let other = render(&properties, &Palette::default());
+                assert!(differing(&image, &other) < 48 * 32 / 20, "colouring {colouring}, math64 {math64}");
+            }
+        }
+    }
+
+    #[test]
+    fn image_has_the_requested_size() {
+        let properties = properties(37, 23);
+        assert_eq!(render(&properties, &Palette::default()).len(), 37 * 23 * 4);
+        assert_eq!(render32(&Properties32::from(properties), &Palette::default()).len(), 37 * 23 * 4);
+    }
+}
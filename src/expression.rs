@@ -0,0 +1,496 @@
+//! Iteration formulas typed by the user.
+//!
+//! An expression in `z` and `c` such as `z^3 - z + c` or `sin(z) * c` is parsed
+//! into a tree and compiled to the body of `custom_step32` in the shaders, which
+//! replaces the built-in formulas while `Properties::custom` is set. Along with
+//! the value the generated code computes the derivative by the chain rule, so
+//! that distance estimation works for every formula.
+
+use std::{fmt::{self, Write}, str::FromStr};
+
+/// Limit on the size of a formula, to keep the shader quick to compile.
+const MAX_NODES: usize = 256;
+/// Limit on how deep parentheses, calls, signs and exponents nest,
+/// so that parsing them recursively cannot overflow the stack.
+const MAX_DEPTH: usize = 64;
+/// Limit on integer exponents, which the shaders raise to by repeated multiplication.
+const MAX_EXPONENT: f32 = 64.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Function {
+    Sin, Cos, Tan,
+    Sinh, Cosh, Tanh,
+    Exp, Log, Sqrt,
+}
+
+const FUNCTIONS: &[(&str, Function)] = &[
+    ("sin", Function::Sin), ("cos", Function::Cos), ("tan", Function::Tan),
+    ("sinh", Function::Sinh), ("cosh", Function::Cosh), ("tanh", Function::Tanh),
+    ("exp", Function::Exp), ("log", Function::Log), ("sqrt", Function::Sqrt),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Operator {
+    Add, Sub, Mul, Div, Pow,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) enum Node {
+    Z,
+    C,
+    Number([f32; 2]),
+    Neg(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Box<Node>),
+}
+
+impl Node {
+    /// The number if the node is a real one.
+    pub(crate) fn real_constant(&self) -> Option<f32> {
+        match self {
+            Node::Number([re, 0.0]) => Some(*re),
+            _ => None,
+        }
+    }
+
+    /// Highest power of `z` of a polynomial in `z`, `None` for anything else.
+    fn degree(&self) -> Option<f32> {
+        match self {
+            Node::Z => Some(1.0),
+            Node::C | Node::Number(_) => Some(0.0),
+            Node::Neg(a) => a.degree(),
+            Node::Binary(Operator::Add | Operator::Sub, a, b) => Some(a.degree()?.max(b.degree()?)),
+            Node::Binary(Operator::Mul, a, b) => Some(a.degree()? + b.degree()?),
+            Node::Binary(Operator::Div, a, b) if b.degree()? == 0.0 => a.degree(),
+            Node::Binary(Operator::Pow, a, b) => match (a.degree()?, b.real_constant()) {
+                (0.0, _) if b.degree()? == 0.0 => Some(0.0),
+                (d, Some(e)) if e >= 0.0 => Some(d * e),
+                _ => None,
+            },
+            Node::Call(_, a) if a.degree()? == 0.0 => Some(0.0),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number([f32; 2]),
+    Name(String),
+    Symbol(char),
+}
+
+/// Splits `text` into tokens, each with the column it starts at.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut k = 0;
+
+    while k < chars.len() {
+        let start = k;
+        let ch = chars[k];
+        if ch.is_whitespace() {
+            k += 1;
+        }
+        else if ch.is_ascii_digit() || ch == '.' {
+            while k < chars.len() && (chars[k].is_ascii_digit() || chars[k] == '.') {
+                k += 1;
+            }
+            if k < chars.len() && (chars[k] == 'e' || chars[k] == 'E') {
+                let mut j = k + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    k = j;
+                    while k < chars.len() && chars[k].is_ascii_digit() {
+                        k += 1;
+                    }
+                }
+            }
+
+            let number: String = chars[start..k].iter().collect();
+            let x: f32 = number.parse()
+                .map_err(|_| format!("invalid number {number} at {}", start + 1))?;
+            if !x.is_finite() {
+                return Err(format!("number {number} at {} is out of range", start + 1));
+            }
+
+            // 2i is imaginary, but not the 2 of 2if
+            let imaginary = k < chars.len() && chars[k] == 'i'
+                && !(k + 1 < chars.len() && chars[k + 1].is_alphanumeric());
+            if imaginary {
+                k += 1;
+            }
+            tokens.push((start + 1, Token::Number(if imaginary { [0.0, x] } else { [x, 0.0] })));
+        }
+        else if ch.is_alphabetic() {
+            while k < chars.len() && chars[k].is_alphanumeric() {
+                k += 1;
+            }
+            tokens.push((start + 1, Token::Name(chars[start..k].iter().collect())));
+        }
+        else if "+-*/^()".contains(ch) {
+            tokens.push((start + 1, Token::Symbol(ch)));
+            k += 1;
+        }
+        else {
+            return Err(format!("unexpected '{ch}' at {}", start + 1));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, from the lowest precedence up:
+/// sums, products, signs, powers and single values.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// Levels of nesting around the current token.
+    depth: usize,
+    /// Nodes created so far, counted as they are so that long formulas
+    /// are rejected before their trees grow too deep to walk.
+    nodes: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    fn next_symbol(&mut self, symbols: &str) -> Option<char> {
+        match self.peek() {
+            Some(&Token::Symbol(symbol)) if symbols.contains(symbol) => {
+                self.position += 1;
+                Some(symbol)
+            }
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> String {
+        match self.tokens.get(self.position) {
+            Some((column, Token::Number(_))) => format!("unexpected number at {column}"),
+            Some((column, Token::Name(name))) => format!("unexpected {name} at {column}"),
+            Some((column, Token::Symbol(symbol))) => format!("unexpected '{symbol}' at {column}"),
+            None => "unexpected end of the formula".to_string(),
+        }
+    }
+
+    /// Runs `parse` a level of nesting deeper.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Node, String>) -> Result<Node, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(format!("the formula is nested too deep, it can have at most {MAX_DEPTH} levels"));
+        }
+        self.depth += 1;
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    /// Counts `node` against the size limit.
+    fn node(&mut self, node: Node) -> Result<Node, String> {
+        self.nodes += 1;
+        if self.nodes > MAX_NODES {
+            return Err(format!("the formula is too long, it can have at most {MAX_NODES} terms"));
+        }
+        Ok(node)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        let mut node = self.product()?;
+        while let Some(symbol) = self.next_symbol("+-") {
+            let operator = if symbol == '+' { Operator::Add } else { Operator::Sub };
+            let right = self.product()?;
+            node = self.node(Node::Binary(operator, Box::new(node), Box::new(right)))?;
+        }
+        Ok(node)
+    }
+
+    /// Also multiplies values written next to each other, as in `2z` or `c sin(z)`.
+    fn product(&mut self) -> Result<Node, String> {
+        let mut node = self.sign()?;
+        loop {
+            let operator = match self.next_symbol("*/") {
+                Some('*') => Operator::Mul,
+                Some(_) => Operator::Div,
+                None => match self.peek() {
+                    Some(Token::Number(_) | Token::Name(_) | Token::Symbol('(')) => Operator::Mul,
+                    _ => return Ok(node),
+                },
+            };
+            let right = self.sign()?;
+            node = self.node(Node::Binary(operator, Box::new(node), Box::new(right)))?;
+        }
+    }
+
+    fn sign(&mut self) -> Result<Node, String> {
+        match self.next_symbol("+-") {
+            Some('-') => match self.nested(Self::sign)? {
+                Node::Number([re, im]) => Ok(Node::Number([-re, -im])),
+                node => self.node(Node::Neg(Box::new(node))),
+            },
+            Some(_) => self.nested(Self::sign),
+            None => self.power(),
+        }
+    }
+
+    /// `^` binds tighter than a sign on its left and looser than one on its right,
+    /// `-z^-2` is `-(z^(-2))`.
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.value()?;
+        if self.next_symbol("^").is_none() {
+            return Ok(base);
+        }
+
+        let column = self.tokens.get(self.position).map_or(0, |(column, _)| *column);
+        let exponent = self.nested(Self::sign)?;
+        if let Some(e) = exponent.real_constant() {
+            if e == e.round() && e.abs() > MAX_EXPONENT {
+                return Err(format!("exponent at {column} is larger than {MAX_EXPONENT}"));
+            }
+        }
+        self.node(Node::Binary(Operator::Pow, Box::new(base), Box::new(exponent)))
+    }
+
+    fn value(&mut self) -> Result<Node, String> {
+        let Some((column, token)) = self.tokens.get(self.position).cloned() else {
+            return Err(self.unexpected());
+        };
+        match token {
+            Token::Number(number) => {
+                self.position += 1;
+                self.node(Node::Number(number))
+            }
+            Token::Symbol('(') => {
+                self.position += 1;
+                let node = self.nested(Self::sum)?;
+                self.next_symbol(")").ok_or_else(|| self.unexpected())?;
+                Ok(node)
+            }
+            Token::Name(name) => {
+                self.position += 1;
+                match name.as_str() {
+                    "z" => self.node(Node::Z),
+                    "c" => self.node(Node::C),
+                    "i" => self.node(Node::Number([0.0, 1.0])),
+                    name => {
+                        let Some(&(_, function)) = FUNCTIONS.iter().find(|(n, _)| *n == name) else {
+                            return Err(format!("unknown name {name} at {column}, expected z, c, i or a function"));
+                        };
+                        self.next_symbol("(").ok_or_else(|| self.unexpected())?;
+                        let argument = self.nested(Self::sum)?;
+                        self.next_symbol(")").ok_or_else(|| self.unexpected())?;
+                        self.node(Node::Call(function, Box::new(argument)))
+                    }
+                }
+            }
+            Token::Symbol(_) => Err(self.unexpected()),
+        }
+    }
+}
+
+/// A formula `f(z, c)`, iterated as `z -> f(z, c)`.
+#[derive(Clone, Debug)]
+pub struct Expression {
+    text: String,
+    root: Node,
+}
+
+impl Expression {
+    pub(crate) fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Growth of the orbits near infinity as the exponent `d` of `z^d`, which
+    /// smooth colouring needs: the degree of a polynomial in `z` and 2 otherwise.
+    pub fn degree(&self) -> f32 {
+        self.root.degree().filter(|&d| d >= 2.0).unwrap_or(2.0)
+    }
+
+    /// The body of `custom_step32` in the shaders, which gets `z` and its
+    /// derivative in `w` and `dw`, and `c` and its derivative in `c` and `dc`.
+    pub fn wgsl(&self) -> String {
+        let mut generator = Generator { code: String::new(), temporaries: 0 };
+        let (value, derivative) = generator.node(&self.root);
+        let _ = writeln!(generator.code, "    *z = {value};");
+        let _ = writeln!(generator.code, "    *der = {derivative};");
+        generator.code
+    }
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self::from_str("z^2 + c").expect("z^2 + c is a valid formula")
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { tokens: tokenize(text)?, position: 0, depth: 0, nodes: 0 };
+        let root = parser.sum()?;
+        if parser.position < parser.tokens.len() {
+            return Err(parser.unexpected());
+        }
+        Ok(Expression { text: text.trim().to_string(), root })
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Writes a WGSL statement for every operation, with the value in `v<n>`
+/// and its derivative in `d<n>`.
+struct Generator {
+    code: String,
+    temporaries: usize,
+}
+
+const ZERO: &str = "Complex32(0.0, 0.0)";
+const ONE: &str = "Complex32(1.0, 0.0)";
+
+impl Generator {
+    /// Binds `value` and the derivative, which may refer to the value by the name it gets.
+    fn bind(&mut self, value: String, derivative: impl FnOnce(&str) -> String) -> (String, String) {
+        let n = self.temporaries;
+        self.temporaries += 1;
+        let (v, d) = (format!("v{n}"), format!("d{n}"));
+        let derivative = derivative(&v);
+        let _ = writeln!(self.code, "    let {v} = {value};");
+        let _ = writeln!(self.code, "    let {d} = {derivative};");
+        (v, d)
+    }
+
+    fn node(&mut self, node: &Node) -> (String, String) {
+        match node {
+            Node::Z => ("w".to_string(), "dw".to_string()),
+            Node::C => ("c".to_string(), "dc".to_string()),
+            Node::Number([re, im]) => (format!("Complex32({re:?}, {im:?})"), ZERO.to_string()),
+            Node::Neg(a) => {
+                let (va, da) = self.node(a);
+                self.bind(format!("-{va}"), |_| format!("-{da}"))
+            }
+            Node::Binary(operator, a, b) => {
+                let (va, da) = self.node(a);
+                let exponent = b.real_constant();
+                let (vb, db) = self.node(b);
+                match operator {
+                    Operator::Add => self.bind(format!("{va} + {vb}"), |_| format!("{da} + {db}")),
+                    Operator::Sub => self.bind(format!("{va} - {vb}"), |_| format!("{da} - {db}")),
+                    Operator::Mul => self.bind(format!("mul32({va}, {vb})"),
+                        |_| format!("mul32({da}, {vb}) + mul32({va}, {db})")),
+                    Operator::Div => self.bind(format!("div32({va}, {vb})"),
+                        |_| format!("div32(mul32({da}, {vb}) - mul32({va}, {db}), mul32({vb}, {vb}))")),
+                    Operator::Pow => match exponent {
+                        Some(0.0) => (ONE.to_string(), ZERO.to_string()),
+                        Some(e) if e > 0.0 && e == e.round() => self.bind(format!("pow32({va}, {e:?})"),
+                            |_| format!("{e:?} * mul32(pow32({va}, {:?}), {da})", e - 1.0)),
+                        Some(e) if e == e.round() => self.bind(format!("div32({ONE}, pow32({va}, {:?}))", -e),
+                            |v| format!("{e:?} * mul32({v}, div32({da}, {va}))")),
+                        Some(e) => self.bind(format!("pow32({va}, {e:?})"),
+                            |v| format!("{e:?} * mul32({v}, div32({da}, {va}))")),
+                        None => self.bind(format!("exp32(mul32({vb}, log32({va})))"),
+                            |v| format!("mul32({v}, mul32({db}, log32({va})) + mul32({vb}, div32({da}, {va})))")),
+                    },
+                }
+            }
+            Node::Call(function, a) => {
+                let (va, da) = self.node(a);
+                let value = match function {
+                    Function::Sin => format!("sin32({va})"),
+                    Function::Cos => format!("cos32({va})"),
+                    Function::Tan => format!("div32(sin32({va}), cos32({va}))"),
+                    Function::Sinh => format!("sinh32({va})"),
+                    Function::Cosh => format!("cosh32({va})"),
+                    Function::Tanh => format!("div32(sinh32({va}), cosh32({va}))"),
+                    Function::Exp => format!("exp32({va})"),
+                    Function::Log => format!("log32({va})"),
+                    Function::Sqrt => format!("sqrt32({va})"),
+                };
+                self.bind(value, |v| match function {
+                    Function::Sin => format!("mul32(cos32({va}), {da})"),
+                    Function::Cos => format!("-mul32(sin32({va}), {da})"),
+                    Function::Tan => format!("div32({da}, mul32(cos32({va}), cos32({va})))"),
+                    Function::Sinh => format!("mul32(cosh32({va}), {da})"),
+                    Function::Cosh => format!("mul32(sinh32({va}), {da})"),
+                    Function::Tanh => format!("div32({da}, mul32(cosh32({va}), cosh32({va})))"),
+                    Function::Exp => format!("mul32({v}, {da})"),
+                    Function::Log => format!("div32({da}, {va})"),
+                    Function::Sqrt => format!("div32({da}, 2.0 * {v})"),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tree of `text` in prefix notation.
+    fn tree(text: &str) -> String {
+        fn prefix(node: &Node) -> String {
+            match node {
+                Node::Z => "z".to_string(),
+                Node::C => "c".to_string(),
+                Node::Number([re, im]) => format!("{re}{im:+}i"),
+                Node::Neg(a) => format!("(neg {})", prefix(a)),
+                Node::Binary(operator, a, b) => format!("({operator:?} {} {})", prefix(a), prefix(b)),
+                Node::Call(function, a) => format!("({function:?} {})", prefix(a)),
+            }
+        }
+        prefix(Expression::from_str(text).unwrap().root())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(tree("z + c * z"), "(Add z (Mul c z))");
+        assert_eq!(tree("(z + c) * z"), "(Mul (Add z c) z)");
+        assert_eq!(tree("z - c - z"), "(Sub (Sub z c) z)");
+        assert_eq!(tree("2z^3"), "(Mul 2+0i (Pow z 3+0i))");
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(tree("-z^2"), "(neg (Pow z 2+0i))");
+        assert_eq!(tree("z^-2"), "(Pow z -2-0i)");
+        assert_eq!(tree("--z"), "(neg (neg z))");
+        assert_eq!(tree("c * -z"), "(Mul c (neg z))");
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(tree("sin(z) * c"), "(Mul (Sin z) c)");
+        assert_eq!(tree("exp(sqrt(z + c))"), "(Exp (Sqrt (Add z c)))");
+    }
+
+    #[test]
+    fn wgsl() {
+        let code = Expression::from_str("-sin(z)").unwrap().wgsl();
+        assert_eq!(code, "    let v0 = sin32(w);\n    let d0 = mul32(cos32(w), dw);\n    \
+            let v1 = -v0;\n    let d1 = -d0;\n    *z = v1;\n    *der = d1;\n");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Expression::from_str("z +").is_err());
+        assert!(Expression::from_str("sin z").is_err());
+        assert!(Expression::from_str("f(z)").is_err());
+        assert!(Expression::from_str("z^100").is_err());
+        assert!(Expression::from_str(&format!("{}z", "z + ".repeat(1000))).is_err());
+    }
+
+    #[test]
+    fn deep_nesting_is_rejected() {
+        let parentheses = format!("{}z{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(Expression::from_str(&parentheses).is_err());
+        assert!(Expression::from_str(&format!("{}z", "-".repeat(100_000))).is_err());
+        assert!(Expression::from_str(&format!("{}z{}", "sin(".repeat(100_000), ")".repeat(100_000))).is_err());
+    }
+}
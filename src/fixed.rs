@@ -0,0 +1,265 @@
+//! Arbitrary-precision fixed-point numbers.
+
+use std::{borrow::Cow, fmt, ops::{Add, Mul, Neg, Sub}, str::FromStr};
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{Signed, ToPrimitive, Zero};
+
+/// A signed number with `frac_bits` binary digits after the point.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fixed {
+    value: BigInt,
+    frac_bits: u32,
+}
+
+/// `x * 2^exp`, without overflowing or underflowing the intermediate results.
+fn ldexp(mut x: f64, mut exp: i64) -> f64 {
+    while exp > 1000 {
+        x *= 2f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 {
+        x *= 2f64.powi(-1000);
+        exp += 1000;
+    }
+    x * 2f64.powi(exp as i32)
+}
+
+impl Fixed {
+    pub fn zero(frac_bits: u32) -> Self {
+        Fixed { value: BigInt::zero(), frac_bits }
+    }
+
+    /// Converts `x` exactly, or truncated to `frac_bits` if it has more digits.
+    pub fn from_f64(x: f64, frac_bits: u32) -> Self {
+        if x == 0.0 || !x.is_finite() {
+            return Self::zero(frac_bits);
+        }
+
+        let bits = x.to_bits();
+        let sign = if bits >> 63 == 0 { 1 } else { -1 };
+        let exponent = ((bits >> 52) & 0x7ff) as i64;
+        let mantissa = if exponent == 0 {
+            (bits & 0xf_ffff_ffff_ffff) << 1
+        }
+        else {
+            (bits & 0xf_ffff_ffff_ffff) | 0x10_0000_0000_0000
+        };
+        // x = mantissa * 2^(exponent - 1075)
+        let shift = exponent - 1075 + frac_bits as i64;
+
+        let value = BigInt::from(mantissa) * sign;
+        let value = if shift >= 0 {
+            value << shift as usize
+        }
+        else {
+            value >> (-shift) as usize
+        };
+
+        Fixed { value, frac_bits }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // keep only as many bits as fit into the mantissa before converting
+        let shift = self.value.bits().saturating_sub(64);
+        let value = (&self.value >> shift).to_f64().unwrap_or(0.0);
+        ldexp(value, shift as i64 - self.frac_bits as i64)
+    }
+
+    pub fn frac_bits(&self) -> u32 {
+        self.frac_bits
+    }
+
+    /// The same number with `frac_bits` binary digits after the point.
+    pub fn with_frac_bits(&self, frac_bits: u32) -> Self {
+        let value = if frac_bits >= self.frac_bits {
+            &self.value << (frac_bits - self.frac_bits) as usize
+        }
+        else {
+            &self.value >> (self.frac_bits - frac_bits) as usize
+        };
+        Fixed { value, frac_bits }
+    }
+
+    pub fn abs(&self) -> Fixed {
+        Fixed { value: self.value.abs(), frac_bits: self.frac_bits }
+    }
+
+    /// Decimal representation with `digits` digits after the point, rounded.
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let scale = BigInt::from(10).pow(digits as u32);
+        let half = (BigInt::from(1) << self.frac_bits as usize) >> 1;
+        let scaled: BigInt = (self.value.abs() * &scale + half) >> self.frac_bits as usize;
+        let integer = &scaled / &scale;
+        let fraction = &scaled % &scale;
+
+        let sign = if self.value.sign() == Sign::Minus && !scaled.is_zero() { "-" } else { "" };
+        if digits == 0 {
+            format!("{sign}{integer}")
+        }
+        else {
+            format!("{sign}{integer}.{fraction:0>digits$}")
+        }
+    }
+
+    /// Brings `other` to the precision of `self`.
+    fn align<'a>(&self, other: &'a Fixed) -> Cow<'a, Fixed> {
+        if other.frac_bits == self.frac_bits {
+            Cow::Borrowed(other)
+        }
+        else {
+            Cow::Owned(other.with_frac_bits(self.frac_bits))
+        }
+    }
+}
+
+impl Add for &Fixed {
+    type Output = Fixed;
+
+    fn add(self, rhs: &Fixed) -> Fixed {
+        Fixed { value: &self.value + &self.align(rhs).value, frac_bits: self.frac_bits }
+    }
+}
+
+impl Sub for &Fixed {
+    type Output = Fixed;
+
+    fn sub(self, rhs: &Fixed) -> Fixed {
+        Fixed { value: &self.value - &self.align(rhs).value, frac_bits: self.frac_bits }
+    }
+}
+
+impl Neg for &Fixed {
+    type Output = Fixed;
+
+    fn neg(self) -> Fixed {
+        Fixed { value: -&self.value, frac_bits: self.frac_bits }
+    }
+}
+
+impl Mul for &Fixed {
+    type Output = Fixed;
+
+    fn mul(self, rhs: &Fixed) -> Fixed {
+        let value = (&self.value * &self.align(rhs).value) >> self.frac_bits as usize;
+        Fixed { value, frac_bits: self.frac_bits }
+    }
+}
+
+/// Largest power of ten a parsed number may have, far past the deepest zoom.
+const MAX_EXPONENT: i32 = 10000;
+/// Largest number of digits a parsed number may have, which take bits like the exponent.
+const MAX_DIGITS: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseFixedError(String);
+
+impl fmt::Display for ParseFixedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid number: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseFixedError {}
+
+impl FromStr for Fixed {
+    type Err = ParseFixedError;
+
+    /// Parses a decimal number like `-0.7436`, `1.5e-20` or `2`,
+    /// with enough precision for all of its digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseFixedError(s.to_string());
+
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(i) => (&s[..i], s[i + 1..].parse::<i32>().map_err(|_| error())?),
+            None => (s, 0),
+        };
+        // the number takes bits in proportion to the exponent
+        if !(-MAX_EXPONENT..=MAX_EXPONENT).contains(&exponent) {
+            return Err(error());
+        }
+        let (negative, mantissa) = match mantissa.strip_prefix('-') {
+            Some(mantissa) => (true, mantissa),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+
+        if integer.is_empty() && fraction.is_empty()
+            || integer.len() + fraction.len() > MAX_DIGITS
+            || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+        {
+            return Err(error());
+        }
+
+        let digits: BigInt = format!("0{integer}{fraction}").parse().map_err(|_| error())?;
+        // value = digits * 10^exponent
+        let exponent = i32::try_from(fraction.len()).ok()
+            .and_then(|len| exponent.checked_sub(len))
+            .ok_or_else(error)?;
+        let frac_bits = 64u32.max(((-exponent) as f64 / std::f64::consts::LOG10_2).ceil() as u32 + 64);
+
+        let value = if exponent >= 0 {
+            (digits * BigInt::from(10).pow(exponent as u32)) << frac_bits as usize
+        }
+        else {
+            let scale = BigInt::from(10).pow((-exponent) as u32);
+            ((digits << frac_bits as usize) + (&scale >> 1)) / scale
+        };
+        let value = if negative { -value } else { value };
+
+        Ok(Fixed { value, frac_bits })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Fixed {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn decimal_round_trips() {
+        assert_eq!(parse("-0.7436").to_decimal(4), "-0.7436");
+        assert_eq!(parse("+2").to_decimal(0), "2");
+        assert_eq!(parse("1.5e-20").to_decimal(21), "0.000000000000000000015");
+        assert_eq!(parse("12.5E3").to_decimal(1), "12500.0");
+        assert_eq!(parse(".25").to_decimal(3), "0.250");
+        let digits = "0.".to_string() + &"7".repeat(400);
+        assert_eq!(parse(&digits).to_decimal(400), digits);
+    }
+
+    #[test]
+    fn f64_round_trips() {
+        for x in [0.0, 1.0, -0.7436438870371587, 1e-300, -5e-324, 1.5e300, f64::MAX] {
+            assert_eq!(Fixed::from_f64(x, 1200).to_f64(), x);
+        }
+        // truncated to the binary digits after the point
+        assert_eq!(Fixed::from_f64(0.75, 1).to_f64(), 0.5);
+    }
+
+    #[test]
+    fn large_numbers_are_rejected() {
+        assert!(Fixed::from_str("1e10001").is_err());
+        assert!(Fixed::from_str("1e-10001").is_err());
+        assert!(Fixed::from_str("1e99999999999").is_err());
+        assert!(Fixed::from_str(&("0.".to_string() + &"1".repeat(1_000_000))).is_err());
+        assert!(Fixed::from_str(&"1".repeat(1_000_000)).is_err());
+    }
+
+    #[test]
+    fn malformed_numbers_are_rejected() {
+        for s in ["", ".", "-", "1.2.3", "1e", "e5", "0x10", "1 ", "--1"] {
+            assert!(Fixed::from_str(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn product_matches_f64() {
+        for (a, b) in [(1.25, -3.5), (0.1, 0.3), (-0.7436438870371587, 0.1318259042053), (1e-30, 3e10)] {
+            let product = (&Fixed::from_f64(a, 200) * &Fixed::from_f64(b, 200)).to_f64();
+            assert!((product - a * b).abs() <= 1e-15 * (a * b).abs(), "{a} * {b}");
+        }
+    }
+}
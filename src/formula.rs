@@ -0,0 +1,56 @@
+//! Escape-time formulas, all of them variations of `z^d + c`.
+//!
+//! The shaders only know the folds in `Properties::fold`: absolute values and
+//! conjugation applied to `z` before it is raised to the power `d`, and the
+//! absolute value of the real part after. A formula made of these is added
+//! to [`FORMULAS`] without touching the shaders.
+
+/// Bits of `Properties::fold`.
+/// `x -> |x|` before the power.
+pub const FOLD_ABS_X: u32 = 1;
+/// `y -> |y|` before the power.
+pub const FOLD_ABS_Y: u32 = 2;
+/// `y -> -y` before the power, after the absolute values.
+pub const FOLD_CONJUGATE: u32 = 4;
+/// `x -> |x|` after the power.
+pub const FOLD_ABS_RE: u32 = 8;
+
+pub struct Formula {
+    pub name: &'static str,
+    pub fold: u32,
+    /// Center and zoom that show the whole fractal for `z^2 + c`.
+    pub view: ([f64; 2], f64),
+}
+
+pub const FORMULAS: &[Formula] = &[
+    Formula {
+        name: "Mandelbrot",
+        fold: 0,
+        view: ([-0.75, 0.0], 1.2),
+    },
+    Formula {
+        name: "Burning Ship",
+        fold: FOLD_ABS_X | FOLD_ABS_Y,
+        view: ([-0.5, -0.55], 1.15),
+    },
+    Formula {
+        name: "Tricorn",
+        fold: FOLD_CONJUGATE,
+        view: ([-0.5, 0.0], 1.35),
+    },
+    Formula {
+        name: "Celtic",
+        fold: FOLD_ABS_RE,
+        view: ([-0.85, 0.0], 1.45),
+    },
+    Formula {
+        name: "Buffalo",
+        fold: FOLD_ABS_X | FOLD_ABS_Y | FOLD_CONJUGATE | FOLD_ABS_RE,
+        view: ([-0.7, 0.65], 1.2),
+    },
+    Formula {
+        name: "Perpendicular",
+        fold: FOLD_ABS_X | FOLD_CONJUGATE,
+        view: ([-0.35, 0.0], 1.2),
+    },
+];
@@ -0,0 +1,162 @@
+//! GPU resources of histogram colouring.
+//!
+//! With `COLOURING_HISTOGRAM` the fractal shader writes the iteration counts of
+//! the pixels into an iteration texture instead of colours. The compute passes of
+//! `histogram.wgsl` then count them into bins between the smallest and the
+//! largest count and sum the bins up, and `fs_histogram` colours every pixel by
+//! the fraction of the pixels with fewer iterations. The palette is spread evenly
+//! over the pixels that way, however deep the view and however many iterations.
+
+use std::{borrow::Cow, mem::size_of};
+
+use crate::{offscreen, shaders};
+
+/// Number of bins, must match `HISTOGRAM_BINS` in the shaders.
+pub const HISTOGRAM_BINS: usize = 4096;
+/// Mean iteration count of the escaped samples of a pixel in r,
+/// times the fraction of its samples that escaped in g.
+pub const ITERATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rg32Float;
+/// Invocations per workgroup of `range` and `count` along x and y.
+const WORKGROUP_SIZE: u32 = 16;
+
+pub struct Histogram {
+    /// Inverted bits of the smallest count, bits of the largest count and the bins.
+    buffer: wgpu::Buffer,
+    iteration_texture: wgpu::Texture,
+    iteration_view: wgpu::TextureView,
+    compute_layout: wgpu::BindGroupLayout,
+    compute_bind_group: wgpu::BindGroup,
+    range_pipeline: wgpu::ComputePipeline,
+    count_pipeline: wgpu::ComputePipeline,
+    accumulate_pipeline: wgpu::ComputePipeline,
+    /// The iteration texture and the bins for `fs_histogram`, in group 1.
+    render_layout: wgpu::BindGroupLayout,
+    render_bind_group: wgpu::BindGroup,
+}
+
+fn bind_group_layout(device: &wgpu::Device, visibility: wgpu::ShaderStages, read_only: bool) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: None,
+    })
+}
+
+fn bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, view: &wgpu::TextureView, buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffer.as_entire_binding(),
+            },
+        ],
+        label: None,
+    })
+}
+
+impl Histogram {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Histogram buffer"),
+            size: ((2 + HISTOGRAM_BINS) * size_of::<u32>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let iteration_texture = offscreen::texture(device, "Iteration texture", ITERATION_FORMAT, width, height);
+        let iteration_view = iteration_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let compute_layout = bind_group_layout(device, wgpu::ShaderStages::COMPUTE, false);
+        let render_layout = bind_group_layout(device, wgpu::ShaderStages::FRAGMENT, true);
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Histogram shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(shaders::HISTOGRAM)),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&compute_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point,
+        });
+
+        Histogram {
+            compute_bind_group: bind_group(device, &compute_layout, &iteration_view, &buffer),
+            render_bind_group: bind_group(device, &render_layout, &iteration_view, &buffer),
+            range_pipeline: pipeline("range"),
+            count_pipeline: pipeline("count"),
+            accumulate_pipeline: pipeline("accumulate"),
+            buffer,
+            iteration_texture,
+            iteration_view,
+            compute_layout,
+            render_layout,
+        }
+    }
+
+    /// Recreates the iteration texture for a window of `width`x`height`.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.iteration_texture = offscreen::texture(device, "Iteration texture", ITERATION_FORMAT, width, height);
+        self.iteration_view = self.iteration_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.compute_bind_group = bind_group(device, &self.compute_layout, &self.iteration_view, &self.buffer);
+        self.render_bind_group = bind_group(device, &self.render_layout, &self.iteration_view, &self.buffer);
+    }
+
+    /// Where the fractal shader writes the iteration counts to.
+    pub fn iteration_view(&self) -> &wgpu::TextureView {
+        &self.iteration_view
+    }
+
+    pub fn render_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.render_layout
+    }
+
+    pub fn render_bind_group(&self) -> &wgpu::BindGroup {
+        &self.render_bind_group
+    }
+
+    /// Records the passes that sum up the bins, after the iteration counts are written.
+    pub fn compute(&self, encoder: &mut wgpu::CommandEncoder) {
+        let size = self.iteration_texture.size();
+        let workgroups = (size.width.div_ceil(WORKGROUP_SIZE), size.height.div_ceil(WORKGROUP_SIZE));
+
+        encoder.clear_buffer(&self.buffer, 0, None);
+        let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Histogram") });
+        cpass.set_bind_group(0, &self.compute_bind_group, &[]);
+        for pipeline in [&self.range_pipeline, &self.count_pipeline] {
+            cpass.set_pipeline(pipeline);
+            cpass.dispatch_workgroups(workgroups.0, workgroups.1, 1);
+        }
+        cpass.set_pipeline(&self.accumulate_pipeline);
+        cpass.dispatch_workgroups(1, 1, 1);
+    }
+}
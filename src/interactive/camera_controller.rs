@@ -1,89 +1,685 @@
+use std::time::Instant;
+
 use winit::{
     dpi::PhysicalPosition,
-    event::{WindowEvent, KeyboardInput, ElementState, VirtualKeyCode, MouseButton, MouseScrollDelta}};
+    event::{WindowEvent, KeyboardInput, ElementState, VirtualKeyCode, MouseButton, MouseScrollDelta, ModifiersState}};
+
+use crate::{
+    expression::Expression,
+    fixed::Fixed,
+    formula::{Formula, FORMULAS},
+    newton::Polynomial,
+    palette::{Palette, INTERPOLATIONS, INTERPOLATION_LINEAR},
+    perturbation::precision,
+    trap::Trap};
+
+/// Values of `Properties::math64`, selecting how pixels are computed.
+pub const MATH32: u32 = 0;
+pub const MATH64: u32 = 1;
+/// Iterate the f32/f64 difference to a high-precision reference orbit.
+pub const PERTURBATION: u32 = 2;
+/// Iterate directly with pairs of f64, only in the 64-bit shader.
+pub const DOUBLE_DOUBLE: u32 = 3;
+
+/// Values of `Properties::colouring`.
+/// Colour by the integer iteration count, in bands.
+pub const COLOURING_ITERATIONS: u32 = 0;
+/// Colour by the continuous iteration count, without bands.
+pub const COLOURING_SMOOTH: u32 = 1;
+/// Shade by the estimated distance to the set, showing its boundary as thin lines.
+pub const COLOURING_DISTANCE: u32 = 2;
+/// Colour by the rank of the continuous iteration count among those of all pixels,
+/// spreading the palette evenly over them.
+pub const COLOURING_HISTOGRAM: u32 = 3;
+/// Colour by the smallest distance of the orbit to the trap, see [`crate::trap`].
+pub const COLOURING_TRAP_DISTANCE: u32 = 4;
+/// Colour by the iteration at which the orbit hits the trap.
+pub const COLOURING_TRAP_ITERATION: u32 = 5;
+/// Colour by the average over the orbit of stripes along the angle of `z`.
+pub const COLOURING_STRIPE: u32 = 6;
+/// Colour by the average over the orbit of where `|z|` lies between the bounds
+/// the triangle inequality puts on it.
+pub const COLOURING_TRIANGLE: u32 = 7;
+/// Colour by the average over the orbit of how sharply it turns.
+pub const COLOURING_CURVATURE: u32 = 8;
+const COLOURINGS: u32 = 9;
+
+/// Values of `Properties::sampling`, placing the samples of a pixel.
+/// On a square grid.
+pub const SAMPLING_GRID: u32 = 0;
+/// Each at a random point of its cell of the grid.
+pub const SAMPLING_JITTERED: u32 = 1;
+/// On the grid, only for pixels that differ strongly from a neighbour.
+pub const SAMPLING_ADAPTIVE: u32 = 2;
+const SAMPLINGS: u32 = 3;
+
+/// Largest `Properties::max_iter`, the reference orbit buffer has room for this many points.
+pub const MAX_ITER: u32 = 1 << 18;
+/// Limits of `Properties::bailout`.
+const MIN_BAILOUT: f32 = 2.0;
+const MAX_BAILOUT: f32 = 65536.0;
+
+/// Limits of `Properties::palette_scale`.
+const MIN_PALETTE_SCALE: f32 = 1.0 / 64.0;
+const MAX_PALETTE_SCALE: f32 = 1024.0;
+/// Step of `Properties::palette_offset`.
+const PALETTE_OFFSET_STEP: f32 = 1.0 / 32.0;
+/// Limits of the magnitude of `Properties::palette_speed`.
+const MIN_PALETTE_SPEED: f32 = 1.0 / 256.0;
+const MAX_PALETTE_SPEED: f32 = 4.0;
+/// Limits of `Properties::stripe_density`, whole numbers keep the stripes
+/// continuous across the negative real axis.
+const MIN_STRIPE_DENSITY: f32 = 1.0;
+const MAX_STRIPE_DENSITY: f32 = 32.0;
+/// Limits of `Properties::light_height`.
+const MIN_LIGHT_HEIGHT: f32 = 1.0 / 16.0;
+const MAX_LIGHT_HEIGHT: f32 = 16.0;
+/// Step of `Properties::specular`, which wraps around from 1 to 0.
+const SPECULAR_STEP: f32 = 0.25;
+
+/// Limits of `Properties::exponent`.
+const MIN_EXPONENT: f32 = 2.0;
+const MAX_EXPONENT: f32 = 16.0;
+/// Smallest zoom for what only the f32 math renders:
+/// real exponents and the Newton fractal.
+const MATH32_ONLY_MIN_ZOOM: f64 = 1e-4;
+/// Smallest zoom of perturbation with f32 deltas. The delta of a pixel is
+/// about `zoom / height`, which stays a normal f32 for windows up to 8192
+/// pixels high, as `f32::MIN_POSITIVE * 8192` is about 1e-34.
+pub const MIN_ZOOM32: f64 = 1e-34;
+
+/// Center and zoom that show the whole fractal or its Julia set for `exponent`.
+/// Apart from the fractal for squaring they are roughly symmetric around zero
+/// and lie within the escape radius `2^(1/(exponent - 1))`.
+fn default_view(formula: &Formula, julia: bool, exponent: f32) -> ([f64; 2], f64) {
+    let radius = 2f64.powf(1.0 / (exponent as f64 - 1.0));
+    if julia { ([0.0, 0.0], (0.75 * radius).max(1.1)) }
+    else if exponent == 2.0 { formula.view }
+    else { ([0.0, 0.0], radius.max(1.1)) }
+}
+
+/// Center and zoom of the plane that is not shown.
+struct View {
+    center: [Fixed; 2],
+    log_zoom: f64,
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Properties {
     pub center: [f64; 2],
+    /// Low parts of `center` for double-double arithmetic.
+    pub center_lo: [f64; 2],
     pub zoom: f64,
-    width: f32, height: f32,
-    i_width: f32, i_height: f32,
-    math64: u32,
-    _padding: u32,
+    pub(crate) width: f32, pub(crate) height: f32,
+    pub(crate) i_width: f32, pub(crate) i_height: f32,
+    pub math64: u32,
+    pub(crate) orbit_len: u32,
+    pub(crate) series_skip: u32,
+    /// Number of iterations after which a point counts as inside the set.
+    pub max_iter: u32,
+    /// Escape radius.
+    pub bailout: f32,
+    /// How escaped points are coloured, one of the `COLOURING_*` constants.
+    pub colouring: u32,
+    /// Nonzero to skip iterating points known to be inside the set: the main
+    /// cardioid, the period 2 bulb and orbits that become periodic.
+    pub interior_checks: u32,
+    /// Nonzero to render the Julia set for `julia_c` instead of the Mandelbrot set.
+    pub julia: u32,
+    /// Exponent `d` of `z^d + c`, integer exponents are iterated by repeated
+    /// multiplication and real ones in polar form. For a custom formula the
+    /// growth of its orbits, see [`Expression::degree`].
+    pub exponent: f32,
+    /// Absolute values and conjugation of the formula, the `FOLD_*` bits of [`crate::formula`].
+    pub fold: u32,
+    pub julia_c: [f64; 2],
+    /// Nonzero to render the Newton fractal of the polynomial in the polynomial
+    /// buffer instead of an escape-time fractal.
+    pub newton: u32,
+    /// Nonzero to iterate the custom formula spliced into the shader
+    /// instead of the built-in ones.
+    pub custom: u32,
+    /// Where the palette starts, as a fraction of it.
+    pub palette_offset: f32,
+    /// How many times the palette repeats over `max_iter` iterations.
+    pub palette_scale: f32,
+    /// How the palette blends between its stops, one of the `INTERPOLATION_*` constants.
+    pub palette_interpolation: u32,
+    pub(crate) palette_stops: u32,
+    /// Seconds the palette has cycled for since the last change of its speed,
+    /// wrapped to a period of the cycle.
+    pub time: f32,
+    /// Palettes per second the colours flow by while cycling, negative to flow backwards.
+    pub palette_speed: f32,
+    /// The orbit trap, one of the `TRAP_*` shapes of [`crate::trap`] and its parameters.
+    pub trap: u32,
+    pub trap_angle: f32,
+    pub trap_center: [f32; 2],
+    pub trap_radius: f32,
+    pub trap_width: f32,
+    /// How many stripes the stripe average colouring puts around the set.
+    pub stripe_density: f32,
+    /// Nonzero to shade escaped points as if lit from `light_angle` in radians
+    /// within the plane and `light_height` above it.
+    pub lighting: u32,
+    pub light_angle: f32,
+    pub light_height: f32,
+    /// Strength of the highlights of the lighting, from 0 to 1.
+    pub specular: f32,
+    /// Samples per pixel, 1, 4, 9 or 16.
+    pub samples: u32,
+    /// How the samples are placed, one of the `SAMPLING_*` constants.
+    pub sampling: u32,
+    /// Nonzero when the render target stores colours as they are, so that the
+    /// shader encodes its linear colours to sRGB, which sRGB targets do themselves.
+    pub encode_srgb: u32,
+    /// Varies the placement of jittered samples, so that the frames of a
+    /// progressive refinement each take different ones.
+    pub seed: u32,
+    /// Pixels of the window per texel of the accumulated image, see `fs_display`.
+    pub pixel_scale: u32,
 }
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Properties32 {
     pub center: [f32; 2],
+    /// Low parts of `center` for double-single arithmetic.
+    pub center_lo: [f32; 2],
     pub zoom: f32,
-    width: f32, height: f32,
-    i_width: f32, i_height: f32,
-    math64: u32,
+    pub(crate) width: f32, pub(crate) height: f32,
+    pub(crate) i_width: f32, pub(crate) i_height: f32,
+    pub math64: u32,
+    pub(crate) orbit_len: u32,
+    pub(crate) series_skip: u32,
+    pub max_iter: u32,
+    pub bailout: f32,
+    pub colouring: u32,
+    pub interior_checks: u32,
+    pub julia: u32,
+    pub exponent: f32,
+    pub fold: u32,
+    pub newton: u32,
+    pub julia_c: [f32; 2],
+    pub custom: u32,
+    pub palette_offset: f32,
+    pub palette_scale: f32,
+    pub palette_interpolation: u32,
+    pub(crate) palette_stops: u32,
+    pub time: f32,
+    pub palette_speed: f32,
+    pub trap: u32,
+    pub trap_center: [f32; 2],
+    pub trap_angle: f32,
+    pub trap_radius: f32,
+    pub trap_width: f32,
+    pub stripe_density: f32,
+    pub lighting: u32,
+    pub light_angle: f32,
+    pub light_height: f32,
+    pub specular: f32,
+    pub samples: u32,
+    pub sampling: u32,
+    pub encode_srgb: u32,
+    pub seed: u32,
+    pub pixel_scale: u32,
+    _padding: u32,
 }
 
 impl Default for Properties {
     fn default() -> Self {
         Properties {
             center: [-0.75, 0.0],
+            center_lo: [0.0, 0.0],
             zoom: 1.2,
             width: 1920.0, height: 1080.0,
             i_width: 1.0 / 1920.0, i_height: 1.0 / 1080.0,
-            math64: 0,
-            _padding: 0,
+            math64: MATH32,
+            orbit_len: 0,
+            series_skip: 0,
+            max_iter: 255,
+            bailout: 2.0,
+            colouring: COLOURING_ITERATIONS,
+            interior_checks: 1,
+            julia: 0,
+            exponent: 2.0,
+            fold: 0,
+            julia_c: [0.0, 0.0],
+            newton: 0,
+            custom: 0,
+            palette_offset: 0.0,
+            palette_scale: 1.0,
+            palette_interpolation: INTERPOLATION_LINEAR,
+            palette_stops: Palette::default().stops().len() as u32,
+            time: 0.0,
+            palette_speed: 0.125,
+            trap: Trap::default().shape,
+            trap_angle: Trap::default().angle,
+            trap_center: Trap::default().center,
+            trap_radius: Trap::default().radius,
+            trap_width: Trap::default().width,
+            stripe_density: 5.0,
+            lighting: 0,
+            light_angle: std::f32::consts::FRAC_PI_4,
+            light_height: 1.0,
+            specular: 0.5,
+            samples: 4,
+            sampling: SAMPLING_GRID,
+            encode_srgb: 1,
+            seed: 0,
+            pixel_scale: 1,
         }
     }
 }
 
+impl Properties {
+    /// Sets the size of the pixel grid the fractal is rendered to.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let width = width as f32;
+        let height = height as f32;
+
+        self.width = width;
+        self.height = height;
+        self.i_width = 1.0 / width;
+        self.i_height = 1.0 / height;
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width as u32, self.height as u32)
+    }
+
+    /// Largest distance of a pixel from the center, in units of `zoom`.
+    pub fn extent(&self) -> [f64; 2] {
+        let aspect = (self.width / self.height) as f64;
+        [aspect.max(1.0), (1.0 / aspect).max(1.0)]
+    }
+
+    /// Whether the exponent is an integer, which the double-single,
+    /// double-double and f64 math need.
+    pub fn integer_exponent(&self) -> bool {
+        self.exponent == self.exponent.floor()
+    }
+
+    /// Whether the fractal can be rendered with perturbation:
+    /// the Mandelbrot set with an integer exponent.
+    pub fn has_perturbation(&self) -> bool {
+        self.julia == 0 && self.integer_exponent() && self.newton == 0 && self.custom == 0
+    }
+
+    /// Whether the fractal can only be rendered with the f32 math.
+    pub fn math32_only(&self) -> bool {
+        !self.integer_exponent() || self.newton != 0 || self.custom != 0
+    }
+}
+
+impl Properties32 {
+    /// Largest distance of a pixel from the center, in units of `zoom`.
+    pub fn extent(&self) -> [f64; 2] {
+        let aspect = (self.width / self.height) as f64;
+        [aspect.max(1.0), (1.0 / aspect).max(1.0)]
+    }
+}
+
 impl From<Properties> for Properties32 {
     fn from(properties: Properties) -> Self {
+        let center = properties.center.map(|c| c as f32);
         Properties32 {
-            center: [
-                properties.center[0] as f32,
-                properties.center[1] as f32,
+            center,
+            center_lo: [
+                ((properties.center[0] - center[0] as f64) + properties.center_lo[0]) as f32,
+                ((properties.center[1] - center[1] as f64) + properties.center_lo[1]) as f32,
             ],
             zoom: properties.zoom as f32,
             width: properties.width, height: properties.height,
             i_width: properties.i_width, i_height: properties.i_height,
-            math64: 0,
+            // double-single has a few bits less than f64 and needs perturbation sooner,
+            // there is no double-double without f64
+            math64: match properties.math64 {
+                // nor for Julia sets and real exponents
+                MATH64 | DOUBLE_DOUBLE if !properties.has_perturbation() => MATH64,
+                MATH64 if properties.zoom < 1e-11 => PERTURBATION,
+                DOUBLE_DOUBLE => PERTURBATION,
+                math64 => math64,
+            },
+            orbit_len: properties.orbit_len,
+            series_skip: properties.series_skip,
+            max_iter: properties.max_iter,
+            bailout: properties.bailout,
+            colouring: properties.colouring,
+            interior_checks: properties.interior_checks,
+            julia: properties.julia,
+            exponent: properties.exponent,
+            fold: properties.fold,
+            newton: properties.newton,
+            julia_c: properties.julia_c.map(|c| c as f32),
+            custom: properties.custom,
+            palette_offset: properties.palette_offset,
+            palette_scale: properties.palette_scale,
+            palette_interpolation: properties.palette_interpolation,
+            palette_stops: properties.palette_stops,
+            time: properties.time,
+            palette_speed: properties.palette_speed,
+            trap: properties.trap,
+            trap_center: properties.trap_center,
+            trap_angle: properties.trap_angle,
+            trap_radius: properties.trap_radius,
+            trap_width: properties.trap_width,
+            stripe_density: properties.stripe_density,
+            lighting: properties.lighting,
+            light_angle: properties.light_angle,
+            light_height: properties.light_height,
+            specular: properties.specular,
+            samples: properties.samples,
+            sampling: properties.sampling,
+            encode_srgb: properties.encode_srgb,
+            seed: properties.seed,
+            pixel_scale: properties.pixel_scale,
+            _padding: 0,
         }
     }
 }
 
+/// What an event changed, and so what has to be drawn again.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    None,
+    /// Only what is drawn over the image, the Julia preview.
+    Overlay,
+    /// The colours of the image, which is refined again.
+    Image,
+    /// The plane or the fractal shown, which also needs a new reference orbit.
+    View,
+}
 
 pub struct CameraController {
     window_size: (f64, f64),
     properties: Properties,
+    /// Center of the view, with as many bits as the zoom needs.
+    /// `properties.center` and `properties.center_lo` are derived from it.
+    center: [Fixed; 2],
+    /// Base 2 logarithm of `properties.zoom`.
+    log_zoom: f64,
+    /// Smallest `log_zoom` the shader's numbers can represent,
+    /// for the Mandelbrot set and for Julia sets, which have no perturbation.
+    min_log_zoom: f64,
+    julia_min_log_zoom: f64,
+    /// The Julia set while the Mandelbrot set is shown and vice versa.
+    other_view: View,
     speed: f64,
     mouse_position: PhysicalPosition<f64>,
     is_mouse_left_pressed: bool,
+    /// Prefer double-double over perturbation where its precision suffices.
+    double_double: bool,
+    /// Grow `properties.max_iter` with the zoom instead of keeping it fixed.
+    auto_iter: bool,
+    /// Show the Julia set for the point under the cursor in a corner.
+    julia_preview: bool,
+    /// Index into `FORMULAS`, whose folds are in `properties.fold`.
+    formula: usize,
+    /// The polynomial of the Newton fractal.
+    polynomial: Polynomial,
+    /// The formula spliced into the shader, iterated while `properties.custom` is set.
+    custom: Expression,
+    /// The palette in the palette texture, and the index of the built-in one it is
+    /// or the one to go to from a loaded one.
+    palette: Palette,
+    builtin_palette: usize,
+    /// Advance `properties.time` with every frame, and when the last frame was.
+    cycling: bool,
+    last_frame: Option<Instant>,
+    modifiers: ModifiersState,
 }
 
 impl CameraController {
     pub fn new(speed: f64, width: u32, height: u32) -> Self {
-        Self {
+        let properties = Properties::default();
+        let julia_view = default_view(&FORMULAS[0], true, properties.exponent);
+        let mut camera_controller = Self {
             window_size: (width as f64, height as f64),
             speed,
-            properties: Default::default(),
+            properties,
+            center: properties.center.map(|c| Fixed::from_f64(c, precision(properties.zoom))),
+            log_zoom: properties.zoom.log2(),
+            min_log_zoom: f64::MIN_POSITIVE.log2(),
+            julia_min_log_zoom: f64::MIN_POSITIVE.log2(),
+            other_view: View {
+                center: julia_view.0.map(|c| Fixed::from_f64(c, precision(julia_view.1))),
+                log_zoom: julia_view.1.log2(),
+            },
             mouse_position: Default::default(),
             is_mouse_left_pressed: Default::default(),
-        }
+            double_double: false,
+            auto_iter: true,
+            julia_preview: true,
+            formula: 0,
+            polynomial: Polynomial::default(),
+            custom: Expression::default(),
+            palette: Palette::default(),
+            builtin_palette: 0,
+            cycling: false,
+            last_frame: None,
+            modifiers: ModifiersState::empty(),
+        };
+        camera_controller.update_window_size(width, height);
+        camera_controller
     }
 
     pub fn properties(&self) -> Properties {
         self.properties
     }
 
-    pub fn properties32(&self) -> Properties32 {
-        Properties32::from(self.properties)
+    pub fn center(&self) -> &[Fixed; 2] {
+        &self.center
+    }
+
+    /// The center as decimal strings, with as many digits as the zoom resolves.
+    pub fn center_decimal(&self) -> [String; 2] {
+        let digits = (-self.properties.zoom.log10()).ceil().max(0.0) as usize + 4;
+        [self.center[0].to_decimal(digits), self.center[1].to_decimal(digits)]
+    }
+
+    /// Moves the view to `center` and, if given, to `zoom`.
+    pub fn set_view(&mut self, center: [Fixed; 2], zoom: Option<f64>) {
+        self.center = center;
+        if let Some(zoom) = zoom {
+            self.log_zoom = zoom.log2().clamp(self.min_log_zoom(), 5f64.log2());
+        }
+        self.update_view();
+    }
+
+    /// Limits zooming in to what the numbers in the shader can represent,
+    /// for the Mandelbrot set and for Julia sets.
+    pub fn set_min_zoom(&mut self, zoom: f64, julia_zoom: f64) {
+        self.min_log_zoom = zoom.log2();
+        self.julia_min_log_zoom = julia_zoom.log2();
+        self.other_view.log_zoom = self.other_view.log_zoom.max(
+            if self.properties.julia != 0 { self.min_log_zoom } else { self.julia_min_log_zoom });
+        self.log_zoom = self.log_zoom.max(self.min_log_zoom());
+        self.update_view();
+    }
+
+    fn min_log_zoom(&self) -> f64 {
+        if self.properties.math32_only() { MATH32_ONLY_MIN_ZOOM.log2() }
+        else if self.properties.julia != 0 { self.julia_min_log_zoom }
+        else { self.min_log_zoom }
+    }
+
+    /// The point of the shown plane under the cursor.
+    pub fn cursor_point(&self) -> [f64; 2] {
+        let extent = self.properties.extent();
+        let mouse = [self.mouse_position.x, self.mouse_position.y];
+        [0, 1].map(|i| self.properties.center[i] + mouse[i] * extent[i] * self.properties.zoom)
+    }
+
+    /// Properties of the Julia set for the point under the cursor, in a square
+    /// a third of the size of the window, while the Mandelbrot set is shown.
+    pub fn julia_preview(&self) -> Option<Properties> {
+        if !self.julia_preview || self.properties.julia != 0 || self.properties.newton != 0 {
+            return None;
+        }
+
+        let size = (self.window_size.0.min(self.window_size.1) / 3.0) as u32;
+        if size == 0 {
+            return None;
+        }
+        let mut preview = self.properties;
+        preview.resize(size, size);
+        preview.julia = 1;
+        preview.julia_c = self.cursor_point();
+        (preview.center, preview.zoom) = default_view(self.formula(), true, preview.exponent);
+        preview.center_lo = [0.0, 0.0];
+        preview.math64 = MATH32;
+        preview.max_iter = Properties::default().max_iter;
+        // the histogram is of the whole window
+        if preview.colouring == COLOURING_HISTOGRAM {
+            preview.colouring = COLOURING_SMOOTH;
+        }
+        // and it is drawn straight into the window, without the sample texture
+        if preview.sampling == SAMPLING_ADAPTIVE {
+            preview.sampling = SAMPLING_GRID;
+        }
+        Some(preview)
+    }
+
+    /// Switches between the Mandelbrot set and the Julia set for `c`,
+    /// or for the last `c` if not given, keeping the view of the other.
+    pub fn toggle_julia(&mut self, c: Option<[f64; 2]>) {
+        let log_zoom = std::mem::replace(&mut self.log_zoom, self.other_view.log_zoom);
+        let center = std::mem::replace(&mut self.center, self.other_view.center.clone());
+        self.other_view = View { center, log_zoom };
+
+        self.properties.julia ^= 1;
+        if let Some(c) = c {
+            self.properties.julia_c = c;
+        }
+        self.update_view();
+    }
+
+    pub fn formula(&self) -> &'static Formula {
+        &FORMULAS[self.formula]
+    }
+
+    pub fn polynomial(&self) -> &Polynomial {
+        &self.polynomial
+    }
+
+    /// Shows the Newton fractal of `polynomial`.
+    pub fn set_polynomial(&mut self, polynomial: Polynomial) {
+        self.polynomial = polynomial;
+        self.properties.newton = 1;
+        self.reset_views();
+    }
+
+    /// Switches between the Newton fractal and the escape-time fractals.
+    pub fn toggle_newton(&mut self) {
+        self.properties.newton ^= 1;
+        self.reset_views();
+    }
+
+    pub fn custom(&self) -> &Expression {
+        &self.custom
+    }
+
+    /// Iterates `custom`, which must be the formula the shader was compiled with.
+    pub fn set_custom(&mut self, custom: Expression) {
+        self.properties.exponent = custom.degree();
+        self.properties.custom = 1;
+        self.properties.newton = 0;
+        self.custom = custom;
+        self.reset_views();
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.properties.palette_stops = palette.stops().len() as u32;
+        self.palette = palette;
+    }
+
+    pub fn trap(&self) -> Trap {
+        let properties = &self.properties;
+        Trap {
+            shape: properties.trap,
+            center: properties.trap_center,
+            angle: properties.trap_angle,
+            radius: properties.trap_radius,
+            width: properties.trap_width,
+        }
+    }
+
+    pub fn set_trap(&mut self, trap: Trap) {
+        let properties = &mut self.properties;
+        properties.trap = trap.shape;
+        properties.trap_center = trap.center;
+        properties.trap_angle = trap.angle;
+        properties.trap_radius = trap.radius;
+        properties.trap_width = trap.width;
+    }
+
+    /// Whether the palette is cycling, which needs a frame after every frame.
+    pub fn cycling(&self) -> bool {
+        self.cycling
+    }
+
+    /// Advances the palette cycle by the time since the last frame.
+    pub fn tick(&mut self) {
+        if !self.cycling {
+            self.last_frame = None;
+            return;
+        }
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            // keep the time short enough for f32 to resolve frames after hours
+            let period = 1.0 / self.properties.palette_speed.abs();
+            self.properties.time = (self.properties.time + (now - last_frame).as_secs_f32()) % period;
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Sets the speed of the cycle, moving how far it got into the offset
+    /// so that the colours do not jump.
+    fn set_palette_speed(&mut self, speed: f32) {
+        let properties = &mut self.properties;
+        properties.palette_offset = (properties.palette_offset + properties.palette_speed * properties.time).rem_euclid(1.0);
+        properties.time = 0.0;
+        properties.palette_speed = speed;
+    }
+
+    /// The view of the shown plane that shows it whole.
+    fn default_view(&self) -> ([f64; 2], f64) {
+        if self.properties.newton != 0 { self.polynomial.view() }
+        // there is no telling for custom formulas
+        else if self.properties.custom != 0 { ([0.0, 0.0], 2.0) }
+        else { default_view(self.formula(), self.properties.julia != 0, self.properties.exponent) }
+    }
+
+    /// Shows both planes whole, after the formula or the exponent changed
+    /// the old views do not fit the new sets.
+    fn reset_views(&mut self) {
+        let exponent = self.properties.exponent;
+        let (center, zoom) =
+            if self.properties.custom != 0 { ([0.0, 0.0], 2.0) }
+            else { default_view(self.formula(), self.properties.julia == 0, exponent) };
+        self.other_view = View {
+            center: center.map(|c| Fixed::from_f64(c, 64)),
+            log_zoom: zoom.log2(),
+        };
+        let (center, zoom) = self.default_view();
+        self.set_view(center.map(|c| Fixed::from_f64(c, 64)), Some(zoom));
+    }
+
+    pub fn auto_iter(&self) -> bool {
+        self.auto_iter
     }
 
     pub fn mouse_position(&self) -> PhysicalPosition<f64> {
         self.mouse_position
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+    pub fn process_events(&mut self, event: &WindowEvent) -> Change {
         match event {
             WindowEvent::KeyboardInput {
                 input: KeyboardInput {
@@ -94,46 +690,250 @@ impl CameraController {
                 ..
             } => {
                 let is_pressed = *state == ElementState::Pressed;
-                let update = is_pressed == true;
+                let update = |change| if is_pressed { change } else { Change::None };
                 match keycode {
                     VirtualKeyCode::H | VirtualKeyCode::Left => {
                         self.move_center(PhysicalPosition::new(-self.speed * self.properties.zoom, 0.0));
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::J | VirtualKeyCode::Down => {
                         self.move_center(PhysicalPosition::new(0.0, -self.speed * self.properties.zoom));
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::K | VirtualKeyCode::Up => {
                         self.move_center(PhysicalPosition::new(0.0, self.speed * self.properties.zoom));
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::L | VirtualKeyCode::Right => {
                         self.move_center(PhysicalPosition::new(self.speed * self.properties.zoom, 0.0));
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::A | VirtualKeyCode::PageUp => {
                         self.zoom(PhysicalPosition::new(0.0, 0.0), -self.speed);
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::S | VirtualKeyCode::PageDown => {
                         self.zoom(PhysicalPosition::new(0.0, 0.0), self.speed);
-                        update
+                        update(Change::View)
                     }
                     VirtualKeyCode::Space => {
-                        self.properties = Default::default();
-                        update
+                        let (center, zoom) = self.default_view();
+                        self.set_view(center.map(|c| Fixed::from_f64(c, 64)), Some(zoom));
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::W => {
+                        if is_pressed {
+                            self.julia_preview = !self.julia_preview;
+                        }
+                        update(Change::Overlay)
+                    }
+                    VirtualKeyCode::Tab => {
+                        // the Julia set for the point under the cursor,
+                        // the Newton fractal has none
+                        if is_pressed && self.properties.newton == 0 {
+                            let c = (self.properties.julia == 0).then(|| self.cursor_point());
+                            self.toggle_julia(c);
+                            Change::View
+                        }
+                        else {
+                            Change::None
+                        }
+                    }
+                    VirtualKeyCode::I | VirtualKeyCode::U => {
+                        if is_pressed {
+                            let max_iter = self.properties.max_iter;
+                            self.properties.max_iter =
+                                if *keycode == VirtualKeyCode::I { max_iter.saturating_mul(2).min(MAX_ITER) }
+                                else { (max_iter / 2).max(1) };
+                            self.auto_iter = false;
+                        }
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::O => {
+                        if is_pressed {
+                            self.auto_iter = !self.auto_iter;
+                            self.update_max_iter();
+                        }
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::B | VirtualKeyCode::V => {
+                        if is_pressed {
+                            let bailout = self.properties.bailout;
+                            self.properties.bailout =
+                                if *keycode == VirtualKeyCode::B { (bailout * 2.0).min(MAX_BAILOUT) }
+                                else { (bailout / 2.0).max(MIN_BAILOUT) };
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::C => {
+                        if is_pressed {
+                            self.properties.colouring = (self.properties.colouring + 1) % COLOURINGS;
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::P => {
+                        if is_pressed {
+                            self.properties.interior_checks ^= 1;
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::M | VirtualKeyCode::N => {
+                        // steps of 1, or of 0.1 with Shift
+                        if is_pressed && self.properties.custom == 0 {
+                            let step = if self.modifiers.shift() { 0.1 } else { 1.0 };
+                            let step = if *keycode == VirtualKeyCode::M { step } else { -step };
+                            let exponent = ((self.properties.exponent + step) * 10.0).round() / 10.0;
+                            self.properties.exponent = exponent.clamp(MIN_EXPONENT, MAX_EXPONENT);
+                            self.reset_views();
+                        }
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::F => {
+                        // backwards with Shift, from a custom formula back to the last built-in one
+                        if is_pressed && self.properties.custom != 0 {
+                            self.properties.custom = 0;
+                            self.properties.exponent = 2.0;
+                            self.reset_views();
+                        }
+                        else if is_pressed {
+                            let step = if self.modifiers.shift() { FORMULAS.len() - 1 } else { 1 };
+                            self.formula = (self.formula + step) % FORMULAS.len();
+                            self.properties.fold = self.formula().fold;
+                            self.reset_views();
+                        }
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::X => {
+                        if is_pressed {
+                            self.toggle_newton();
+                        }
+                        update(Change::View)
+                    }
+                    VirtualKeyCode::Z => {
+                        // backwards with Shift
+                        if is_pressed {
+                            let builtin = Palette::builtin();
+                            let step = if self.modifiers.shift() { builtin.len() - 1 } else { 1 };
+                            self.builtin_palette = (self.builtin_palette + step) % builtin.len();
+                            self.set_palette(builtin[self.builtin_palette].clone());
+                        }
+                        update(Change::Image)
                     }
-                    _ => false,
+                    VirtualKeyCode::Y => {
+                        if is_pressed {
+                            self.properties.palette_interpolation = (self.properties.palette_interpolation + 1) % INTERPOLATIONS;
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Comma | VirtualKeyCode::Period => {
+                        if is_pressed {
+                            let step = if *keycode == VirtualKeyCode::Period { PALETTE_OFFSET_STEP } else { -PALETTE_OFFSET_STEP };
+                            self.properties.palette_offset = (self.properties.palette_offset + step).rem_euclid(1.0);
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::LBracket | VirtualKeyCode::RBracket => {
+                        if is_pressed {
+                            let scale = self.properties.palette_scale;
+                            self.properties.palette_scale =
+                                if *keycode == VirtualKeyCode::RBracket { (scale * 2.0).min(MAX_PALETTE_SCALE) }
+                                else { (scale / 2.0).max(MIN_PALETTE_SCALE) };
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Q => {
+                        // reverse with Shift
+                        if is_pressed {
+                            if self.modifiers.shift() {
+                                self.set_palette_speed(-self.properties.palette_speed);
+                            }
+                            else {
+                                self.cycling = !self.cycling;
+                            }
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Minus | VirtualKeyCode::Equals => {
+                        if is_pressed {
+                            let speed = self.properties.palette_speed;
+                            let magnitude =
+                                if *keycode == VirtualKeyCode::Equals { (speed.abs() * 2.0).min(MAX_PALETTE_SPEED) }
+                                else { (speed.abs() / 2.0).max(MIN_PALETTE_SPEED) };
+                            self.set_palette_speed(magnitude.copysign(speed));
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Apostrophe => {
+                        // fewer with Shift
+                        if is_pressed {
+                            let step = if self.modifiers.shift() { -1.0 } else { 1.0 };
+                            self.properties.stripe_density = (self.properties.stripe_density + step)
+                                .clamp(MIN_STRIPE_DENSITY, MAX_STRIPE_DENSITY);
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Key1 | VirtualKeyCode::Key2 | VirtualKeyCode::Key3 | VirtualKeyCode::Key4 => {
+                        if is_pressed {
+                            let side = match keycode {
+                                VirtualKeyCode::Key1 => 1,
+                                VirtualKeyCode::Key2 => 2,
+                                VirtualKeyCode::Key3 => 3,
+                                _ => 4,
+                            };
+                            self.properties.samples = side * side;
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Key5 => {
+                        if is_pressed {
+                            self.properties.sampling = (self.properties.sampling + 1) % SAMPLINGS;
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::Backslash => {
+                        // the strength of the highlights with Shift
+                        if is_pressed {
+                            if self.modifiers.shift() {
+                                let specular = self.properties.specular + SPECULAR_STEP;
+                                self.properties.specular = if specular > 1.0 { 0.0 } else { specular };
+                            }
+                            else {
+                                self.properties.lighting ^= 1;
+                            }
+                        }
+                        update(Change::Image)
+                    }
+                    VirtualKeyCode::D => {
+                        if is_pressed {
+                            self.double_double = !self.double_double;
+                            self.update_math64();
+                        }
+                        update(Change::View)
+                    }
+                    _ => Change::None,
                 }
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = *modifiers;
+                Change::None
+            }
             WindowEvent::MouseInput {
                 button: MouseButton::Left,
                 state,
                 ..
             } => {
                 self.is_mouse_left_pressed = *state == ElementState::Pressed;
-                false
+                Change::None
+            },
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } if self.properties.newton == 0 => {
+                // the Julia set for the clicked point, or back to the Mandelbrot set
+                let c = (self.properties.julia == 0).then(|| self.cursor_point());
+                self.toggle_julia(c);
+                Change::View
             },
             WindowEvent::CursorMoved { device_id: _, position, .. } => {
                 let (width, height) = self.window_size;
@@ -148,14 +948,24 @@ impl CameraController {
 
                 self.mouse_position = curr_position;
 
-                if self.is_mouse_left_pressed {
+                if self.modifiers.ctrl() && self.properties.lighting != 0 {
+                    // the light comes from the direction of the cursor seen from the center
+                    let (x, y) = (curr_position.x * width, curr_position.y * height);
+                    self.properties.light_angle = y.atan2(x) as f32;
+                    Change::Image
+                }
+                else if self.is_mouse_left_pressed {
                     self.move_center(PhysicalPosition::new(
                         -dx * self.properties.zoom * 2.0,
                         -dy * self.properties.zoom));
-                    true
+                    Change::View
+                }
+                else if self.julia_preview().is_some() {
+                    // the preview follows the cursor
+                    Change::Overlay
                 }
                 else {
-                    false
+                    Change::None
                 }
             }
             WindowEvent::TouchpadMagnify { delta, phase: _, .. } => {
@@ -167,43 +977,97 @@ impl CameraController {
                     MouseScrollDelta::PixelDelta(pos) => (pos.x * 0.001, pos.y * 0.001),
                 };
 
+                if self.modifiers.ctrl() && self.properties.lighting != 0 {
+                    // raises the light when scrolling up
+                    let height = self.properties.light_height * (1.0 + delta as f32);
+                    self.properties.light_height = height.clamp(MIN_LIGHT_HEIGHT, MAX_LIGHT_HEIGHT);
+                    return Change::Image;
+                }
                 self.zoom(self.mouse_position, -delta)
             }
-            _ => false,
+            _ => Change::None,
         }
     }
 
-    fn zoom(&mut self, center: PhysicalPosition<f64>, delta: f64) -> bool {
+    fn zoom(&mut self, center: PhysicalPosition<f64>, delta: f64) -> Change {
         if delta > 0.0 && self.properties.zoom >= 5.0 {
-            return false
+            return Change::None
+        }
+        if delta < 0.0 && self.log_zoom <= self.min_log_zoom() {
+            return Change::None
         }
 
         let factor = 1.0 + delta;
 
-        self.properties.zoom *= factor;
+        self.log_zoom = (self.log_zoom + factor.log2()).max(self.min_log_zoom());
+        self.update_view();
 
         self.move_center(PhysicalPosition::new(
                 center.x * (1.0 - factor) * self.properties.zoom * 2.0,
                 center.y * (1.0 - factor) * self.properties.zoom));
 
-        self.properties.math64 = if self.properties.zoom < 1.0 / 10_000.0 { 1 } else { 0 };
-        true
+        Change::View
+    }
+
+    /// Derives the uniforms from the center and zoom.
+    fn update_view(&mut self) {
+        self.properties.zoom = self.log_zoom.exp2();
+
+        let bits = precision(self.properties.zoom);
+        for (i, center) in self.center.iter_mut().enumerate() {
+            if center.frac_bits() < bits {
+                *center = center.with_frac_bits(bits);
+            }
+
+            let hi = center.to_f64();
+            self.properties.center[i] = hi;
+            self.properties.center_lo[i] = (&*center - &Fixed::from_f64(hi, bits)).to_f64();
+        }
+
+        self.update_math64();
+        self.update_max_iter();
+    }
+
+    /// In automatic mode, raises the iteration limit with the depth of the zoom,
+    /// where the boundary of the set needs ever more iterations to resolve.
+    fn update_max_iter(&mut self) {
+        if self.auto_iter {
+            let depth = (-self.properties.zoom.log10()).max(0.0);
+            self.properties.max_iter = ((255.0 + 100.0 * depth) as u32).min(MAX_ITER);
+        }
+    }
+
+    /// Picks the cheapest math precise enough for the current zoom.
+    fn update_math64(&mut self) {
+        let zoom = self.properties.zoom;
+        self.properties.math64 =
+            if self.properties.math32_only() { MATH32 }
+            else if self.properties.julia != 0 {
+                if zoom < 1e-13 { DOUBLE_DOUBLE }
+                else if zoom < 1e-4 { MATH64 }
+                else { MATH32 }
+            }
+            else if self.double_double && (1e-28..1e-13).contains(&zoom) { DOUBLE_DOUBLE }
+            else if zoom < 1e-13 { PERTURBATION }
+            else if zoom < 1e-4 { MATH64 }
+            else { MATH32 };
     }
 
     fn move_center(&mut self, delta: PhysicalPosition<f64>) {
-        self.properties.center[0] += delta.x;
-        self.properties.center[1] += delta.y;
+        for (center, delta) in self.center.iter_mut().zip([delta.x, delta.y]) {
+            *center = &*center + &Fixed::from_f64(delta, center.frac_bits());
+        }
+        self.update_view();
     }
 
     pub fn update_window_size(&mut self, width: u32, height: u32) {
         self.window_size = (width as f64, height as f64);
-        let width = width as f32;
-        let height = height as f32;
+        self.properties.resize(width, height);
+    }
 
-        self.properties.width = width;
-        self.properties.height = height;
-        self.properties.i_width = width;
-        self.properties.i_height = height;
+    /// Sets the format of the window, whether it is sRGB decides who encodes the colours.
+    pub fn update_surface_format(&mut self, format: wgpu::TextureFormat) {
+        self.properties.encode_srgb = !format.describe().srgb as u32;
     }
 }
 
@@ -1 +1,2 @@
 pub mod camera_controller;
+pub mod prompt;
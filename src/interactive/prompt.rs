@@ -0,0 +1,50 @@
+/// What a typed character did to a [`Prompt`].
+pub enum PromptEvent {
+    Changed,
+    Submitted(String),
+    Cancelled,
+}
+
+/// Single line of text typed into the window and shown in the title bar.
+pub struct Prompt {
+    label: &'static str,
+    text: String,
+    error: Option<String>,
+}
+
+impl Prompt {
+    pub fn new(label: &'static str, text: String) -> Self {
+        Self { label, text, error: None }
+    }
+
+    /// Handles a character received by the window:
+    /// Enter submits the text, Esc cancels and Backspace deletes.
+    pub fn input(&mut self, c: char) -> PromptEvent {
+        self.error = None;
+        match c {
+            '\r' | '\n' => PromptEvent::Submitted(self.text.clone()),
+            '\u{1b}' => PromptEvent::Cancelled,
+            '\u{8}' | '\u{7f}' => {
+                self.text.pop();
+                PromptEvent::Changed
+            }
+            c if !c.is_control() => {
+                self.text.push(c);
+                PromptEvent::Changed
+            }
+            _ => PromptEvent::Changed,
+        }
+    }
+
+    /// Shows `error` until the text is edited again.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn title(&self) -> String {
+        match &self.error {
+            Some(error) => format!("{}: {}_ | {}", self.label, self.text, error),
+            None => format!("{}: {}_", self.label, self.text),
+        }
+    }
+}
@@ -1,6 +1,19 @@
 mod interactive;
+mod fixed;
+mod histogram;
+mod offscreen;
+mod refinement;
+mod sampling;
+mod shaders;
+pub mod cpu;
+pub mod expression;
+pub mod formula;
+pub mod newton;
+pub mod palette;
+pub mod perturbation;
+pub mod trap;
 
-use std::{borrow::Cow, time::{Instant, Duration}};
+use std::{borrow::Cow, path::Path, str::FromStr, time::{Instant, Duration}};
 
 use wgpu::{util::DeviceExt, Backend, DeviceType, Features};
 use winit::{
@@ -9,7 +22,19 @@ use winit::{
     window::{Window, Fullscreen},
 };
 
-use interactive::camera_controller::CameraController;
+use fixed::Fixed;
+use histogram::{Histogram, ITERATION_FORMAT};
+use refinement::{Refinement, ACCUMULATE, ACCUMULATION_FORMAT};
+use sampling::{Adaptive, SAMPLE_FORMAT};
+use expression::Expression;
+use newton::Polynomial;
+use trap::Trap;
+use palette::{Palette, INTERPOLATION_LINEAR, INTERPOLATION_SMOOTH, INTERPOLATION_STEP, MAX_STOPS};
+use interactive::{
+    camera_controller::{CameraController, Change, COLOURING_ITERATIONS, COLOURING_SMOOTH, COLOURING_DISTANCE, COLOURING_HISTOGRAM, COLOURING_TRAP_DISTANCE, COLOURING_TRAP_ITERATION, COLOURING_STRIPE, COLOURING_TRIANGLE, COLOURING_CURVATURE, SAMPLING_GRID, SAMPLING_JITTERED, SAMPLING_ADAPTIVE, MAX_ITER, MIN_ZOOM32, PERTURBATION},
+    prompt::{Prompt, PromptEvent}};
+pub use interactive::camera_controller::{Properties, Properties32};
+use perturbation::{ReferenceOrbit, SeriesApproximation, SERIES_TERMS, SERIES_TOLERANCE32, SERIES_TOLERANCE64};
 
 fn backend_str(backend: Backend) -> &'static str {
     match backend {
@@ -33,6 +58,219 @@ fn type_str(adapter_info: DeviceType) -> &'static str {
     }
 }
 
+fn colouring_str(colouring: u32) -> &'static str {
+    match colouring {
+        COLOURING_ITERATIONS     => "iterations",
+        COLOURING_SMOOTH         => "smooth",
+        COLOURING_DISTANCE       => "distance",
+        COLOURING_HISTOGRAM      => "histogram",
+        COLOURING_TRAP_DISTANCE  => "trap distance",
+        COLOURING_TRAP_ITERATION => "trap iteration",
+        COLOURING_STRIPE         => "stripe average",
+        COLOURING_TRIANGLE       => "triangle inequality average",
+        COLOURING_CURVATURE      => "curvature average",
+        _                        => "unknown",
+    }
+}
+
+fn sampling_str(sampling: u32) -> &'static str {
+    match sampling {
+        SAMPLING_GRID     => "grid",
+        SAMPLING_JITTERED => "jittered",
+        SAMPLING_ADAPTIVE => "adaptive",
+        _                 => "unknown",
+    }
+}
+
+fn interpolation_str(interpolation: u32) -> &'static str {
+    match interpolation {
+        INTERPOLATION_LINEAR => "linear",
+        INTERPOLATION_SMOOTH => "smooth",
+        INTERPOLATION_STEP   => "step",
+        _                    => "unknown",
+    }
+}
+
+/// Writes properties that need no reference orbit in the layout of the loaded shader.
+fn write_properties(queue: &wgpu::Queue, buffer: &wgpu::Buffer, properties: Properties, float64: bool) {
+    if float64 {
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[properties]));
+    }
+    else {
+        queue.write_buffer(buffer, 0, bytemuck::cast_slice(&[Properties32::from(properties)]));
+    }
+}
+
+/// Writes the reference orbit and its series approximation for the view of
+/// `camera_controller` to the GPU in the layout of the loaded shader when it
+/// renders with perturbation, returning the length of the orbit and the
+/// iterations the series skips, or zeros without perturbation.
+fn upload_orbit(
+    queue: &wgpu::Queue,
+    orbit_buffer: &wgpu::Buffer,
+    series_buffer: &wgpu::Buffer,
+    camera_controller: &CameraController,
+    float64: bool) -> (u32, u32)
+{
+    let properties = camera_controller.properties();
+
+    if float64 {
+        if properties.math64 == PERTURBATION {
+            let orbit = ReferenceOrbit::new(camera_controller.center(), properties.max_iter, properties.exponent as u32, properties.fold);
+            let series = SeriesApproximation::new(&orbit, properties.zoom, properties.extent(), SERIES_TOLERANCE64);
+            queue.write_buffer(orbit_buffer, 0, bytemuck::cast_slice(orbit.points()));
+            queue.write_buffer(series_buffer, 0, bytemuck::cast_slice(series.coefficients()));
+            return (orbit.points().len() as u32, series.skip());
+        }
+    }
+    else if Properties32::from(properties).math64 == PERTURBATION {
+        let orbit = ReferenceOrbit::new(camera_controller.center(), properties.max_iter, properties.exponent as u32, properties.fold);
+        let series = SeriesApproximation::new(&orbit, properties.zoom, properties.extent(), SERIES_TOLERANCE32);
+        queue.write_buffer(orbit_buffer, 0, bytemuck::cast_slice(&orbit.points32()));
+        queue.write_buffer(series_buffer, 0, bytemuck::cast_slice(&series.coefficients32()));
+        return (orbit.points().len() as u32, series.skip());
+    }
+    (0, 0)
+}
+
+/// Parses "re im [magnification]" into a center and a zoom.
+fn parse_view(text: &str) -> Result<([Fixed; 2], Option<f64>), String> {
+    let mut words = text.split_whitespace();
+    let (Some(re), Some(im)) = (words.next(), words.next()) else {
+        return Err("expected: re im [magnification]".to_string());
+    };
+    let center = [
+        Fixed::from_str(re).map_err(|e| e.to_string())?,
+        Fixed::from_str(im).map_err(|e| e.to_string())?,
+    ];
+    let zoom = match words.next() {
+        Some(magnification) => match magnification.parse::<f64>() {
+            Ok(magnification) if magnification > 0.0 => Some(1.0 / magnification),
+            _ => return Err(format!("invalid magnification: {magnification}")),
+        },
+        None => None,
+    };
+    if words.next().is_some() {
+        return Err("expected: re im [magnification]".to_string());
+    }
+    Ok((center, zoom))
+}
+
+/// What the text typed into the prompt is for.
+enum PromptTarget {
+    /// "re im [magnification]" to go to.
+    View,
+    /// Polynomial of the Newton fractal.
+    Polynomial,
+    /// Custom formula `f(z, c)`.
+    Formula,
+    /// File to load a palette from.
+    LoadPalette,
+    /// File to save the palette to.
+    SavePalette,
+    /// Shape and parameters of the orbit trap.
+    Trap,
+}
+
+impl PromptTarget {
+    /// What submitting the prompt changes.
+    fn change(&self) -> Change {
+        match self {
+            PromptTarget::View | PromptTarget::Polynomial | PromptTarget::Formula => Change::View,
+            PromptTarget::LoadPalette | PromptTarget::Trap => Change::Image,
+            PromptTarget::SavePalette => Change::None,
+        }
+    }
+}
+
+/// The render pipelines of the fractal shader. `colour` and `display` render
+/// to the window, the others to offscreen textures.
+struct RenderPipelines {
+    /// Renders the fractal in colour, for the Julia preview.
+    colour: wgpu::RenderPipeline,
+    /// Adds the fractal in colour to the accumulation texture.
+    accumulate: wgpu::RenderPipeline,
+    /// Renders the iteration counts for histogram colouring.
+    iterations: wgpu::RenderPipeline,
+    /// Colours the iteration counts by their histogram.
+    histogram: wgpu::RenderPipeline,
+    /// Renders a sample per pixel for adaptive sampling.
+    samples: wgpu::RenderPipeline,
+    /// Supersamples the pixels that differ from their neighbours.
+    adaptive: wgpu::RenderPipeline,
+    /// Shows the mean of the accumulated frames.
+    display: wgpu::RenderPipeline,
+}
+
+/// Layouts of the render pipelines, by the textures they read in group 1.
+struct PipelineLayouts {
+    /// The properties alone.
+    fractal: wgpu::PipelineLayout,
+    /// `fs_histogram` also reads the iteration counts.
+    histogram: wgpu::PipelineLayout,
+    /// `fs_adaptive` also reads the samples.
+    adaptive: wgpu::PipelineLayout,
+    /// `fs_display` also reads the accumulated frames.
+    display: wgpu::PipelineLayout,
+}
+
+/// Compiles the shader with `custom` spliced in and creates the render pipelines,
+/// returning the errors of either instead of panicking on them.
+fn create_render_pipelines(
+    device: &wgpu::Device,
+    layouts: &PipelineLayouts,
+    variant: shaders::Variant,
+    custom: &Expression,
+    format: wgpu::TextureFormat) -> Result<RenderPipelines, String>
+{
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(Cow::Owned(shaders::source(variant, custom))),
+    });
+
+    let create = |layout, entry_point, format, blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point,
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    });
+    let render_pipelines = RenderPipelines {
+        colour: create(&layouts.fractal, "fs_main", format, None),
+        accumulate: create(&layouts.fractal, "fs_main", ACCUMULATION_FORMAT, Some(ACCUMULATE)),
+        iterations: create(&layouts.fractal, "fs_main", ITERATION_FORMAT, None),
+        histogram: create(&layouts.histogram, "fs_histogram", ACCUMULATION_FORMAT, Some(ACCUMULATE)),
+        samples: create(&layouts.fractal, "fs_main", SAMPLE_FORMAT, None),
+        adaptive: create(&layouts.adaptive, "fs_adaptive", ACCUMULATION_FORMAT, Some(ACCUMULATE)),
+        display: create(&layouts.display, "fs_display", format, None),
+    };
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => {
+            log::error!("{error}");
+            // the title bar has room for a single line
+            Err(error.to_string().split_whitespace().collect::<Vec<_>>().join(" "))
+        }
+        None => Ok(render_pipelines),
+    }
+}
+
 pub async fn run(event_loop: EventLoop<()>, window: Window) {
     let size = window.inner_size();
 
@@ -59,7 +297,7 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
     println!("\t        {}", adapter_info.driver_info);
 
     // SHADER_FLOAT64 feature is only available on Vulkan
-    // it is needed for zooming past 1000x
+    // without it, zooming past 1000x uses emulated double-single math
     let features = match adapter_info.backend {
         Backend::Vulkan => Features::SHADER_FLOAT64,
         _ => Features::empty(),
@@ -72,7 +310,9 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 label: None,
                 features,
                 // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                limits: wgpu::Limits::downlevel_webgl2_defaults()
+                // The reference orbit for perturbation needs storage buffers and histogram
+                // colouring compute shaders, WebGL2 has neither and is not supported.
+                limits: wgpu::Limits::downlevel_defaults()
                     .using_resolution(adapter.limits()),
             },
             None,
@@ -82,22 +322,24 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
 
     let float64 = features == Features::SHADER_FLOAT64;
 
-    // Load the shaders from disk
-    // use the 64-bit shader only when 64-bit math is available
-    let shader = if float64 {
-        device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader64.wgsl"))),
-        })
-    }
-    else {
-        device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader32.wgsl"))),
-        })
-    };
+    // use the 64-bit shader only when 64-bit math is available,
+    // otherwise emulate it with pairs of f32
+    let variant = if float64 { shaders::Variant::Float64 } else { shaders::Variant::DoubleSingle };
 
     let mut camera_controller = CameraController::new(0.02, size.width, size.height);
+    // the deltas of perturbation are f64 or f32,
+    // Julia sets go as deep as double-double or double-single
+    if float64 {
+        camera_controller.set_min_zoom(1e-300, 1e-28);
+    }
+    else {
+        camera_controller.set_min_zoom(MIN_ZOOM32, 1e-11);
+    }
+    // coordinates typed into the title bar, opened with G,
+    // the polynomial of the Newton fractal, opened with R,
+    // a custom formula, opened with E, a palette file, opened with T,
+    // or the orbit trap, opened with ;
+    let mut prompt: Option<(PromptTarget, Prompt)> = None;
     let mut f11_state_prev = ElementState::Released;
     let mut esc_state_prev = ElementState::Released;
     let mut frame_time = Duration::new(1, 0);
@@ -109,6 +351,59 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+    // room for the longest reference orbit in the layout of the loaded shader
+    let orbit_point_size = if float64 { std::mem::size_of::<[f64; 2]>() } else { std::mem::size_of::<[f32; 2]>() };
+    let orbit_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Reference orbit buffer"),
+        size: ((MAX_ITER as usize + 1) * orbit_point_size) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let series_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Series approximation buffer"),
+        size: (SERIES_TERMS * orbit_point_size) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    // the Julia set inset has its own uniforms, it never uses the reference orbit
+    let preview_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Julia preview buffer"),
+            contents: bytemuck::cast_slice(&[camera_controller.properties()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+    // fs_display shows the accumulated frames with the properties of the window,
+    // the fractal shader renders them with those of the frame
+    let display_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Display buffer"),
+            contents: bytemuck::cast_slice(&[camera_controller.properties()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+    let polynomial_buffer = device.create_buffer_init(
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Newton polynomial buffer"),
+            contents: bytemuck::cast_slice(&[camera_controller.polynomial().polynomial32()]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+    // stops of the palette, rgb and position in alpha
+    let palette_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Palette texture"),
+        size: wgpu::Extent3d { width: MAX_STOPS as u32, height: 1, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D1,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let palette_view = palette_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
     let properties_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -120,7 +415,47 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                     min_binding_size: None,
                 },
                 count: None,
-            }
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D1,
+                    multisampled: false,
+                },
+                count: None,
+            },
         ],
         label: Some("aspect_bind_group_layout"),
     });
@@ -131,11 +466,81 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
             wgpu::BindGroupEntry {
                 binding: 0,
                 resource: properties_buffer.as_entire_binding(),
-            }
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: orbit_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: series_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: polynomial_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&palette_view),
+            },
         ],
         label: Some("aspect_bind_group"),
     });
 
+    let preview_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &properties_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: preview_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: orbit_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: series_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: polynomial_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&palette_view),
+            },
+        ],
+        label: Some("preview_bind_group"),
+    });
+
+    let display_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &properties_bind_group_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: display_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: orbit_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: series_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: polynomial_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: wgpu::BindingResource::TextureView(&palette_view),
+            },
+        ],
+        label: Some("display_bind_group"),
+    });
+
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
         bind_group_layouts: &[
@@ -144,28 +549,60 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
         push_constant_ranges: &[],
     });
 
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
-    let swapchain_format = swapchain_capabilities.formats[0];
+    let mut histogram = Histogram::new(&device, size.width, size.height);
+    let histogram_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[
+            &properties_bind_group_layout,
+            histogram.render_layout(),
+        ],
+        push_constant_ranges: &[],
+    });
 
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+    let mut adaptive = Adaptive::new(&device, size.width, size.height);
+    let adaptive_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: None,
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(swapchain_format.into())],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
+        bind_group_layouts: &[
+            &properties_bind_group_layout,
+            adaptive.layout(),
+        ],
+        push_constant_ranges: &[],
     });
 
+    let mut refinement = Refinement::new(&device, size.width, size.height);
+    let display_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[
+            &properties_bind_group_layout,
+            refinement.layout(),
+        ],
+        push_constant_ranges: &[],
+    });
+
+    let pipeline_layouts = PipelineLayouts {
+        fractal: pipeline_layout,
+        histogram: histogram_pipeline_layout,
+        adaptive: adaptive_pipeline_layout,
+        display: display_pipeline_layout,
+    };
+    // length of the reference orbit and iterations skipped by its series,
+    // computed again when the view changes
+    let mut orbit = (0, 0);
+    let mut orbit_stale = true;
+
+    let swapchain_capabilities = surface.get_capabilities(&adapter);
+    // the colours are linear, an sRGB surface encodes them in hardware
+    // and for the others the shader does, the image looks the same on both
+    let swapchain_format = swapchain_capabilities.formats.iter()
+        .copied()
+        .find(|format| format.describe().srgb)
+        .unwrap_or(swapchain_capabilities.formats[0]);
+    camera_controller.update_surface_format(swapchain_format);
+
+    // recreated with every custom formula
+    let mut render_pipelines = create_render_pipelines(&device, &pipeline_layouts, variant, camera_controller.custom(), swapchain_format)
+        .expect("Failed to compile the shader");
+
     let mut config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: swapchain_format,
@@ -182,9 +619,15 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
         // Have the closure take ownership of the resources.
         // `event_loop.run` never returns, therefore we must do this to ensure
         // the resources are properly cleaned up.
-        let _ = (&instance, &adapter, &shader, &pipeline_layout);
+        let _ = (&instance, &adapter, &pipeline_layouts);
 
-        *control_flow = ControlFlow::Wait;
+        // redraw continuously while the palette cycles,
+        // and when the next frame of the refinement is due
+        *control_flow = match refinement.next_frame() {
+            _ if camera_controller.cycling() => ControlFlow::Poll,
+            Some(next_frame) => ControlFlow::WaitUntil(next_frame),
+            None => ControlFlow::Wait,
+        };
         let start = Instant::now();
         match event {
             Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => *control_flow = ControlFlow::Exit,
@@ -193,16 +636,99 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 config.width = size.width;
                 config.height = size.height;
                 camera_controller.update_window_size(size.width, size.height);
-                if float64 {
-                    queue.write_buffer(&properties_buffer, 0, bytemuck::cast_slice(&[camera_controller.properties()]));
-                }
-                else {
-                    queue.write_buffer(&properties_buffer, 0, bytemuck::cast_slice(&[camera_controller.properties32()]));
-                }
+                histogram.resize(&device, size.width, size.height);
+                adaptive.resize(&device, size.width, size.height);
+                refinement.resize(&device, size.width, size.height);
+                // the series depends on the extent of the view
+                orbit_stale = true;
                 surface.configure(&device, &config);
                 // On macos the window needs to be redrawn manually after resizing
                 window.request_redraw();
             }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter(c), .. } if prompt.is_some() => {
+                let Some((target, p)) = prompt.as_mut() else { return };
+                match p.input(c) {
+                    PromptEvent::Changed => window.set_title(&p.title()),
+                    PromptEvent::Cancelled => {
+                        prompt = None;
+                        window.request_redraw();
+                    }
+                    PromptEvent::Submitted(text) => {
+                        let result = match target {
+                            PromptTarget::View => parse_view(&text)
+                                .map(|(center, zoom)| camera_controller.set_view(center, zoom)),
+                            PromptTarget::Polynomial => Polynomial::from_str(&text)
+                                .map(|polynomial| {
+                                    queue.write_buffer(&polynomial_buffer, 0, bytemuck::cast_slice(&[polynomial.polynomial32()]));
+                                    camera_controller.set_polynomial(polynomial);
+                                }),
+                            PromptTarget::Formula => Expression::from_str(&text)
+                                .and_then(|custom| {
+                                    render_pipelines = create_render_pipelines(&device, &pipeline_layouts, variant, &custom, swapchain_format)?;
+                                    camera_controller.set_custom(custom);
+                                    Ok(())
+                                }),
+                            PromptTarget::LoadPalette => Palette::load(Path::new(&text))
+                                .map(|palette| camera_controller.set_palette(palette)),
+                            PromptTarget::SavePalette => camera_controller.palette().save(Path::new(&text)),
+                            PromptTarget::Trap => Trap::from_str(&text)
+                                .map(|trap| camera_controller.set_trap(trap)),
+                        };
+                        match result {
+                            Ok(()) => {
+                                let change = target.change();
+                                prompt = None;
+                                if change != Change::None {
+                                    orbit_stale |= change == Change::View;
+                                    refinement.restart();
+                                }
+                                window.request_redraw();
+                            }
+                            Err(error) => {
+                                p.set_error(error);
+                                window.set_title(&p.title());
+                            }
+                        }
+                    }
+                }
+            }
+            // keys only edit the prompt while it is open
+            Event::WindowEvent { event: WindowEvent::KeyboardInput { .. }, .. } if prompt.is_some() => {}
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter('g'), .. } => {
+                let [re, im] = camera_controller.center_decimal();
+                let p = Prompt::new("go to (re im magnification)",
+                    format!("{re} {im} {:e}", 1.0 / camera_controller.properties().zoom));
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::View, p));
+            }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter('r'), .. } => {
+                let p = Prompt::new("Newton polynomial (coefficients from the highest degree, or roots r1, r2, ...)",
+                    camera_controller.polynomial().coefficients_str());
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::Polynomial, p));
+            }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter('e'), .. } => {
+                let p = Prompt::new("formula f(z, c)", camera_controller.custom().to_string());
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::Formula, p));
+            }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter('t'), .. } => {
+                let p = Prompt::new("load palette (.map, .ggr, .json or .toml)", String::new());
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::LoadPalette, p));
+            }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter('T'), .. } => {
+                let p = Prompt::new("save palette to (.map, .ggr, .json or .toml)",
+                    format!("{}.toml", camera_controller.palette().name.to_lowercase()));
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::SavePalette, p));
+            }
+            Event::WindowEvent { event: WindowEvent::ReceivedCharacter(';'), .. } => {
+                let p = Prompt::new("orbit trap (point, line, cross, circle or stalk, x y angle radius width)",
+                    camera_controller.trap().to_string());
+                window.set_title(&p.title());
+                prompt = Some((PromptTarget::Trap, p));
+            }
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput {
                     input: KeyboardInput {
@@ -214,7 +740,7 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 // toggle fullscreen with F11
                 if state != f11_state_prev && state == ElementState::Pressed {
                     window.set_fullscreen(
-                        if window.fullscreen() == None {
+                        if window.fullscreen().is_none() {
                             Some(Fullscreen::Borderless(None))
                         }
                         else {
@@ -232,31 +758,92 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                 // exit fullscreen with Esc
                 if state != esc_state_prev
                     && state == ElementState::Pressed
-                    && window.fullscreen() != None
+                    && window.fullscreen().is_some()
                 {
                     window.set_fullscreen(None);
                 }
                 esc_state_prev = state;
             }
             Event::WindowEvent { event, .. } => {
-                let _changed = camera_controller.process_events(&event);
+                match camera_controller.process_events(&event) {
+                    Change::None => {}
+                    // the image is shown again as it is, with the preview over it
+                    Change::Overlay => window.request_redraw(),
+                    change => {
+                        orbit_stale |= change == Change::View;
+                        refinement.restart();
+                        window.request_redraw();
+                    }
+                }
 
-                window.set_title(&format!("Mandelbrot fractal | coords: ({}, {}) | zoom: {}x | frame time: {} ms ({} FPS) | {}x{}",
-                    camera_controller.properties().center[0],
-                    camera_controller.properties().center[1],
-                    1.0 / camera_controller.properties().zoom,
+                let [re, im] = camera_controller.center_decimal();
+                let properties = camera_controller.properties();
+                let name = if properties.custom != 0 {
+                    format!("f(z, c) = {}", camera_controller.custom())
+                }
+                else {
+                    camera_controller.formula().name.to_string()
+                };
+                let mut fractal = if properties.newton != 0 {
+                    let roots = camera_controller.polynomial().roots().iter()
+                        .map(|[re, im]| format!("{re:.4}{im:+.4}i"))
+                        .collect::<Vec<_>>();
+                    format!("Newton fractal for {} with roots {}", camera_controller.polynomial(), roots.join(", "))
+                }
+                else if properties.julia != 0 {
+                    format!("{} Julia set for c = ({}, {})", name, properties.julia_c[0], properties.julia_c[1])
+                }
+                else {
+                    format!("{} fractal", name)
+                };
+                if properties.exponent != 2.0 && properties.newton == 0 && properties.custom == 0 {
+                    fractal += &format!(" of degree {}", properties.exponent);
+                }
+                window.set_title(&format!("{} | coords: ({}, {}) | zoom: {:.3e}x | iterations: {}{} | bailout: {} | colouring: {}{} | palette: {} ({}, offset {}, scale {}{}){} | samples: {} ({}) | interior checks: {} | frame time: {} ms ({} FPS) | {}x{}",
+                    fractal, re, im,
+                    1.0 / properties.zoom,
+                    properties.max_iter,
+                    if camera_controller.auto_iter() { " (auto)" } else { "" },
+                    properties.bailout,
+                    colouring_str(properties.colouring),
+                    match properties.colouring {
+                        COLOURING_TRAP_DISTANCE | COLOURING_TRAP_ITERATION => format!(" ({} trap)", camera_controller.trap().shape_str()),
+                        COLOURING_STRIPE => format!(" (density {})", properties.stripe_density),
+                        _ => String::new(),
+                    },
+                    camera_controller.palette().name,
+                    interpolation_str(properties.palette_interpolation),
+                    properties.palette_offset,
+                    properties.palette_scale,
+                    if camera_controller.cycling() { format!(", cycling at {}/s", properties.palette_speed) } else { String::new() },
+                    if properties.lighting != 0 {
+                        format!(" | light: {:.0}°, height {}, specular {}",
+                            properties.light_angle.to_degrees(), properties.light_height, properties.specular)
+                    } else { String::new() },
+                    properties.samples,
+                    sampling_str(properties.sampling),
+                    if properties.interior_checks != 0 { "on" } else { "off" },
                     frame_time.as_millis(),
                     1_000_000 / frame_time.as_micros(),
                     camera_controller.mouse_position().x, camera_controller.mouse_position().y));
             }
+            Event::MainEventsCleared if camera_controller.cycling() || refinement.due() => window.request_redraw(),
             Event::RedrawRequested(_) => {
                 camera_controller.update_window_size(config.width, config.height);
-                if float64 {
-                    queue.write_buffer(&properties_buffer, 0, bytemuck::cast_slice(&[camera_controller.properties()]));
-                }
-                else {
-                    queue.write_buffer(&properties_buffer, 0, bytemuck::cast_slice(&[camera_controller.properties32()]));
+                camera_controller.tick();
+                if orbit_stale {
+                    orbit = upload_orbit(&queue, &orbit_buffer, &series_buffer, &camera_controller, float64);
+                    orbit_stale = false;
                 }
+                queue.write_texture(
+                    palette_texture.as_image_copy(),
+                    bytemuck::cast_slice(&camera_controller.palette().texels()),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: std::num::NonZeroU32::new(MAX_STOPS as u32 * 16),
+                        rows_per_image: None,
+                    },
+                    wgpu::Extent3d { width: MAX_STOPS as u32, height: 1, depth_or_array_layers: 1 });
                 let frame = surface
                     .get_current_texture()
                     .expect("Failed to acquire next swap chain texture");
@@ -265,7 +852,88 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                     .create_view(&wgpu::TextureViewDescriptor::default());
                 let mut encoder =
                     device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+                let mut properties = camera_controller.properties();
+                (properties.orbit_len, properties.series_skip) = orbit;
+                if let Some(properties) = refinement.frame(properties) {
+                    write_properties(&queue, &properties_buffer, properties, float64);
+                    // the preview fills only the top left of the textures
+                    let (width, height) = properties.size();
+                    let load = if refinement.clear() { wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT) } else { wgpu::LoadOp::Load };
+
+                    // the Newton fractal colours by the root, not the iteration count
+                    let histogram_colouring = properties.colouring == COLOURING_HISTOGRAM && properties.newton == 0;
+                    if histogram_colouring {
+                        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some("Iteration counts"),
+                            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                view: histogram.iteration_view(),
+                                resolve_target: None,
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                    store: true,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                        });
+                        rpass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+                        rpass.set_pipeline(&render_pipelines.iterations);
+                        rpass.set_bind_group(0, &properties_bind_group, &[]);
+                        rpass.draw(0..6, 0..1);
+                        drop(rpass);
+
+                        histogram.compute(&mut encoder);
+                    }
+                    // with histogram colouring the iteration counts are supersampled on the grid instead
+                    let adaptive_sampling = properties.sampling == SAMPLING_ADAPTIVE && !histogram_colouring;
+                    if adaptive_sampling {
+                        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                            label: Some("Samples"),
+                            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                                view: adaptive.sample_view(),
+                                resolve_target: None,
+                                ops: wgpu::Operations {
+                                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                    store: true,
+                                },
+                            })],
+                            depth_stencil_attachment: None,
+                        });
+                        rpass.set_pipeline(&render_pipelines.samples);
+                        rpass.set_bind_group(0, &properties_bind_group, &[]);
+                        rpass.draw(0..6, 0..1);
+                    }
+
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Accumulation"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: refinement.accumulation_view(),
+                            resolve_target: None,
+                            ops: wgpu::Operations { load, store: true },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+                    rpass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+                    if histogram_colouring {
+                        rpass.set_pipeline(&render_pipelines.histogram);
+                        rpass.set_bind_group(1, histogram.render_bind_group(), &[]);
+                    }
+                    else if adaptive_sampling {
+                        rpass.set_pipeline(&render_pipelines.adaptive);
+                        rpass.set_bind_group(1, adaptive.bind_group(), &[]);
+                    }
+                    else {
+                        rpass.set_pipeline(&render_pipelines.accumulate);
+                    }
+                    rpass.set_bind_group(0, &properties_bind_group, &[]);
+                    rpass.draw(0..6, 0..1);
+                    drop(rpass);
+
+                    refinement.advance(properties.sampling, camera_controller.cycling());
+                }
                 {
+                    write_properties(&queue, &display_buffer, refinement.display(properties), float64);
+
                     let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                         label: None,
                         color_attachments: &[Some(wgpu::RenderPassColorAttachment {
@@ -278,13 +946,42 @@ pub async fn run(event_loop: EventLoop<()>, window: Window) {
                         })],
                         depth_stencil_attachment: None,
                     });
-                    rpass.set_pipeline(&render_pipeline);
-                    rpass.set_bind_group(0, &properties_bind_group, &[]);
+                    rpass.set_pipeline(&render_pipelines.display);
+                    rpass.set_bind_group(0, &display_bind_group, &[]);
+                    rpass.set_bind_group(1, refinement.bind_group(), &[]);
                     rpass.draw(0..6, 0..1);
 
                     frame_time = start.elapsed();
                 }
 
+                // Julia set for the point under the cursor, in the bottom right corner
+                if let Some(preview) = camera_controller.julia_preview() {
+                    write_properties(&queue, &preview_buffer, preview, float64);
+                    let (width, height) = preview.size();
+                    let margin = height / 16;
+
+                    let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Julia preview"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Load,
+                                store: true,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                    });
+                    rpass.set_viewport(
+                        config.width.saturating_sub(width + margin) as f32,
+                        config.height.saturating_sub(height + margin) as f32,
+                        width as f32, height as f32,
+                        0.0, 1.0);
+                    rpass.set_pipeline(&render_pipelines.colour);
+                    rpass.set_bind_group(0, &preview_bind_group, &[]);
+                    rpass.draw(0..6, 0..1);
+                }
+
                 queue.submit(Some(encoder.finish()));
                 frame.present();
             }
@@ -0,0 +1,381 @@
+//! Polynomials for the Newton fractal.
+//!
+//! Newton's method `z -> z - p(z) / p'(z)` converges to a root of `p` from
+//! almost every starting point, and the fractal colours every point by the root
+//! it reaches. The shaders need the coefficients to iterate and the roots to
+//! tell the basins apart, so whichever of the two is given, the other is
+//! computed here.
+
+use std::{fmt, str::FromStr};
+
+type Complex = [f64; 2];
+
+/// Highest degree the shaders have room for, must match `MAX_DEGREE` in the shaders.
+pub const MAX_DEGREE: usize = 8;
+
+fn sub(a: Complex, b: Complex) -> Complex {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    [a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]]
+}
+
+fn div(a: Complex, b: Complex) -> Complex {
+    let abs2 = b[0] * b[0] + b[1] * b[1];
+    let product = mul(a, [b[0], -b[1]]);
+    [product[0] / abs2, product[1] / abs2]
+}
+
+fn abs(a: Complex) -> f64 {
+    a[0].hypot(a[1])
+}
+
+/// Value at `z` of the polynomial with `coefficients` from the constant term up.
+fn evaluate(coefficients: &[Complex], z: Complex) -> Complex {
+    let mut sum = [0.0, 0.0];
+    for &c in coefficients.iter().rev() {
+        let product = mul(sum, z);
+        sum = [product[0] + c[0], product[1] + c[1]];
+    }
+    sum
+}
+
+/// Roots of the polynomial with `coefficients` by the Durand-Kerner method,
+/// which refines approximations of all roots at once.
+fn find_roots(coefficients: &[Complex]) -> Vec<Complex> {
+    let degree = coefficients.len() - 1;
+    let leading = coefficients[degree];
+    let monic: Vec<Complex> = coefficients.iter().map(|&c| div(c, leading)).collect();
+
+    // powers of a number that is neither real nor a root of unity
+    let mut roots = Vec::with_capacity(degree);
+    let mut root = [1.0, 0.0];
+    for _ in 0..degree {
+        roots.push(root);
+        root = mul(root, [0.4, 0.9]);
+    }
+
+    for _ in 0..1000 {
+        let mut change: f64 = 0.0;
+        for i in 0..degree {
+            let mut denominator = [1.0, 0.0];
+            for j in (0..degree).filter(|&j| j != i) {
+                denominator = mul(denominator, sub(roots[i], roots[j]));
+            }
+            let delta = div(evaluate(&monic, roots[i]), denominator);
+            if delta.iter().all(|d| d.is_finite()) {
+                roots[i] = sub(roots[i], delta);
+                change = change.max(abs(delta));
+            }
+        }
+        if change < 1e-15 {
+            break;
+        }
+    }
+
+    roots
+}
+
+/// A polynomial of degree 1 to [`MAX_DEGREE`] with complex coefficients.
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    /// From the constant term up, the last one is nonzero.
+    coefficients: Vec<Complex>,
+    roots: Vec<Complex>,
+}
+
+impl Polynomial {
+    /// The polynomial with `coefficients` from the constant term up.
+    pub fn from_coefficients(mut coefficients: Vec<Complex>) -> Result<Self, String> {
+        while coefficients.last() == Some(&[0.0, 0.0]) {
+            coefficients.pop();
+        }
+        Self::check_degree(coefficients.len().saturating_sub(1))?;
+        let roots = find_roots(&coefficients);
+        Ok(Polynomial { coefficients, roots })
+    }
+
+    /// The monic polynomial `(z - roots[0]) * (z - roots[1]) * ...`.
+    pub fn from_roots(roots: Vec<Complex>) -> Result<Self, String> {
+        Self::check_degree(roots.len())?;
+        let mut coefficients = vec![[1.0, 0.0]];
+        for &root in &roots {
+            // multiply by z - root
+            let mut next = vec![[0.0, 0.0]; coefficients.len() + 1];
+            for (k, &c) in coefficients.iter().enumerate() {
+                next[k + 1] = [next[k + 1][0] + c[0], next[k + 1][1] + c[1]];
+                next[k] = sub(next[k], mul(c, root));
+            }
+            coefficients = next;
+        }
+        Ok(Polynomial { coefficients, roots })
+    }
+
+    fn check_degree(degree: usize) -> Result<(), String> {
+        if degree == 0 {
+            Err("the polynomial needs a degree of at least 1".to_string())
+        }
+        else if degree > MAX_DEGREE {
+            Err(format!("the degree can be at most {MAX_DEGREE}"))
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    pub fn degree(&self) -> usize {
+        self.roots.len()
+    }
+
+    /// Coefficients from the constant term up.
+    pub fn coefficients(&self) -> &[[f64; 2]] {
+        &self.coefficients
+    }
+
+    pub fn roots(&self) -> &[[f64; 2]] {
+        &self.roots
+    }
+
+    /// Center and zoom that show all roots with room for their basins around them.
+    pub fn view(&self) -> ([f64; 2], f64) {
+        let n = self.roots.len() as f64;
+        let center = [0, 1].map(|i| self.roots.iter().map(|r| r[i]).sum::<f64>() / n);
+        let radius = self.roots.iter().map(|&r| abs(sub(r, center))).fold(0.0, f64::max);
+        (center, (1.5 * radius).max(1.0))
+    }
+
+    /// The coefficients as typed into the prompt: highest degree first, separated by commas.
+    pub fn coefficients_str(&self) -> String {
+        self.coefficients.iter().rev()
+            .map(|&c| complex_str(c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    pub fn polynomial32(&self) -> Polynomial32 {
+        let mut polynomial = Polynomial32 {
+            degree: self.degree() as u32,
+            ..Default::default()
+        };
+        for (to, from) in polynomial.coefficients.iter_mut().zip(&self.coefficients) {
+            *to = from.map(|x| x as f32);
+        }
+        for (to, from) in polynomial.roots.iter_mut().zip(&self.roots) {
+            *to = from.map(|x| x as f32);
+        }
+        polynomial
+    }
+}
+
+impl Default for Polynomial {
+    /// `z^3 - 1`, whose roots are the cube roots of unity.
+    fn default() -> Self {
+        Self::from_coefficients(vec![[-1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [1.0, 0.0]])
+            .expect("z^3 - 1 has degree 3")
+    }
+}
+
+/// `x` with at most 6 decimals, without trailing zeros.
+fn real_str(x: f64) -> String {
+    let s = format!("{:.6}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" { "0".to_string() } else { s.to_string() }
+}
+
+/// `c` as `a`, `bi` or `a+bi`, the syntax [`Polynomial::from_str`] reads.
+fn complex_str(c: Complex) -> String {
+    let (re, mut im) = (real_str(c[0]), real_str(c[1]));
+    if im == "1" || im == "-1" {
+        im.pop();
+    }
+    match (re.as_str(), im.as_str()) {
+        (_, "0") => re,
+        ("0", _) => format!("{im}i"),
+        (_, im) if im.starts_with('-') => format!("{re}{im}i"),
+        _ => format!("{re}+{im}i"),
+    }
+}
+
+impl fmt::Display for Polynomial {
+    /// Formats as `z^3 - 1`, complex coefficients in parentheses.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (k, &c) in self.coefficients.iter().enumerate().rev() {
+            let mut coefficient = complex_str(c);
+            if coefficient == "0" {
+                continue;
+            }
+
+            // with both a real and an imaginary part
+            let compound = coefficient[1..].contains(['+', '-']);
+            let sign = if coefficient.starts_with('-') && !compound {
+                coefficient.remove(0);
+                "-"
+            }
+            else {
+                "+"
+            };
+            if compound {
+                coefficient = format!("({coefficient})");
+            }
+            if k > 0 && coefficient == "1" {
+                coefficient.clear();
+            }
+            let power = match k {
+                0 => String::new(),
+                1 => "z".to_string(),
+                k => format!("z^{k}"),
+            };
+
+            match (first, sign) {
+                (true, "-") => write!(f, "-")?,
+                (true, _) => {}
+                (false, sign) => write!(f, " {sign} ")?,
+            }
+            write!(f, "{coefficient}{power}")?;
+            first = false;
+        }
+        Ok(())
+    }
+}
+
+/// Parses `a`, `bi` or `a+bi`, where `b` may be left out.
+fn parse_complex(s: &str) -> Result<Complex, String> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let invalid = || format!("invalid number: {s}");
+
+    let Some(body) = s.strip_suffix('i') else {
+        return s.parse::<f64>().ok()
+            .filter(|re| re.is_finite())
+            .map(|re| [re, 0.0])
+            .ok_or_else(invalid);
+    };
+    // the imaginary part starts at the last sign that is not part of an exponent
+    let split = body.char_indices().rev()
+        .find(|&(k, c)| (c == '+' || c == '-') && k > 0 && !body[..k].ends_with(['e', 'E']))
+        .map_or(0, |(k, _)| k);
+    let (re, im) = body.split_at(split);
+
+    let re: f64 = if re.is_empty() { 0.0 } else { re.parse().map_err(|_| invalid())? };
+    let im: f64 = match im {
+        "" | "+" => 1.0,
+        "-" => -1.0,
+        im => im.parse().map_err(|_| invalid())?,
+    };
+    // infinities and NaNs would make every root NaN
+    if !re.is_finite() || !im.is_finite() {
+        return Err(invalid());
+    }
+    Ok([re, im])
+}
+
+impl FromStr for Polynomial {
+    type Err = String;
+
+    /// Parses comma-separated coefficients, highest degree first,
+    /// or the roots after the word `roots`.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (roots, list) = match s.trim().strip_prefix("roots") {
+            Some(list) => (true, list),
+            None => (false, s),
+        };
+        let numbers = list.split(',')
+            .map(parse_complex)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if roots {
+            Self::from_roots(numbers)
+        }
+        else {
+            Self::from_coefficients(numbers.into_iter().rev().collect())
+        }
+    }
+}
+
+/// A polynomial in the layout of `Polynomial` in the shaders.
+#[repr(C)]
+#[derive(Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Polynomial32 {
+    pub degree: u32,
+    _padding: u32,
+    /// From the constant term up.
+    pub coefficients: [[f32; 2]; MAX_DEGREE + 1],
+    pub roots: [[f32; 2]; MAX_DEGREE],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        abs(sub(a, b)) < 1e-9
+    }
+
+    #[test]
+    fn complex_numbers() {
+        assert_eq!(parse_complex("3"), Ok([3.0, 0.0]));
+        assert_eq!(parse_complex("1+i"), Ok([1.0, 1.0]));
+        assert_eq!(parse_complex("-i"), Ok([0.0, -1.0]));
+        assert_eq!(parse_complex("2e-3i"), Ok([0.0, 2e-3]));
+        assert_eq!(parse_complex(" 1.5 - 2i "), Ok([1.5, -2.0]));
+        assert_eq!(parse_complex("1e-3+2E+3i"), Ok([1e-3, 2e3]));
+    }
+
+    #[test]
+    fn invalid_numbers_are_rejected() {
+        for s in ["inf", "nan", "-infi", "1+nani", "1e999", "", "1+", "z", "1+2j"] {
+            assert!(parse_complex(s).is_err(), "{s:?}");
+        }
+    }
+
+    #[test]
+    fn roots_are_parsed() {
+        let polynomial: Polynomial = "roots 1, -1".parse().unwrap();
+        assert_eq!(polynomial.coefficients(), [[-1.0, 0.0], [0.0, 0.0], [1.0, 0.0]]);
+        assert_eq!(polynomial.roots(), [[1.0, 0.0], [-1.0, 0.0]]);
+    }
+
+    #[test]
+    fn leading_zeros_are_stripped() {
+        let polynomial: Polynomial = "0, 0, 1, 2".parse().unwrap();
+        assert_eq!(polynomial.degree(), 1);
+        assert_eq!(polynomial.coefficients(), [[2.0, 0.0], [1.0, 0.0]]);
+        assert!(close(polynomial.roots()[0], [-2.0, 0.0]));
+    }
+
+    #[test]
+    fn degree_is_limited() {
+        assert!(Polynomial::from_str("5").is_err());
+        assert!(Polynomial::from_str("0, 5").is_err());
+        assert!(Polynomial::from_str("roots").is_err());
+        let coefficients = ["1"; MAX_DEGREE + 1].join(", ");
+        assert_eq!(Polynomial::from_str(&coefficients).unwrap().degree(), MAX_DEGREE);
+        assert!(Polynomial::from_str(&format!("1, {coefficients}")).is_err());
+        let roots = ["1"; MAX_DEGREE + 1].join(", ");
+        assert!(Polynomial::from_str(&format!("roots {roots}")).is_err());
+    }
+
+    #[test]
+    fn cube_roots_of_unity() {
+        let polynomial = Polynomial::default();
+        let h = 3f64.sqrt() / 2.0;
+        for expected in [[1.0, 0.0], [-0.5, h], [-0.5, -h]] {
+            assert!(polynomial.roots().iter().any(|&root| close(root, expected)), "{expected:?}");
+        }
+        for &root in polynomial.roots() {
+            assert!(abs(evaluate(polynomial.coefficients(), root)) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        assert_eq!(Polynomial::default().to_string(), "z^3 - 1");
+        assert_eq!(Polynomial::default().coefficients_str(), "1, 0, 0, -1");
+
+        let polynomial: Polynomial = "1+i, -2, 0, 0.5i, -1-2.5i".parse().unwrap();
+        assert_eq!(polynomial.to_string(), "(1+i)z^4 - 2z^3 + 0.5iz + (-1-2.5i)");
+        let parsed: Polynomial = polynomial.coefficients_str().parse().unwrap();
+        assert_eq!(parsed.coefficients(), polynomial.coefficients());
+    }
+}
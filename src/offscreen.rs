@@ -0,0 +1,88 @@
+//! Textures the size of the window that one pass renders to and a later one reads.
+
+/// A texture of `format` for a window of `width`x`height` to render to and read from.
+pub fn texture(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        // a minimized window is 0x0
+        size: wgpu::Extent3d { width: width.max(1), height: height.max(1), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    })
+}
+
+fn bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, view: &wgpu::TextureView) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+        ],
+        label: None,
+    })
+}
+
+/// A texture for a fragment shader to read in binding 0 of group 1.
+pub struct Offscreen {
+    label: &'static str,
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Offscreen {
+    pub fn new(device: &wgpu::Device, label: &'static str, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        let texture = texture(device, label, format, width, height);
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+            label: None,
+        });
+
+        Offscreen {
+            label,
+            bind_group: bind_group(device, &layout, &view),
+            texture,
+            view,
+            layout,
+        }
+    }
+
+    /// Recreates the texture for a window of `width`x`height`.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.texture = texture(device, self.label, self.texture.format(), width, height);
+        self.view = self.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group = bind_group(device, &self.layout, &self.view);
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,430 @@
+//! Colour palettes, gradients through a list of colour stops.
+//!
+//! The shaders get the stops of a palette in a 1D texture, a stop per texel
+//! with its colour in RGB and its position in alpha, and look up the colour of
+//! an iteration count with the offset, scale and interpolation in `Properties`.
+//!
+//! Palettes are read from and written to Fractint `.map` files, GIMP `.ggr`
+//! gradients and JSON or TOML files of the form
+//!
+//! ```toml
+//! name = "Sunset"
+//!
+//! [[stops]]
+//! position = 0.0
+//! colour = "#1a0533"
+//!
+//! [[stops]]
+//! position = 0.6
+//! colour = "#ff7b00"
+//! ```
+
+use std::{fmt::Write, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// Width of the palette texture, must match the shaders.
+pub const MAX_STOPS: usize = 256;
+
+/// Values of `Properties::palette_interpolation`.
+/// Straight lines between the stops.
+pub const INTERPOLATION_LINEAR: u32 = 0;
+/// Eased in and out of every stop.
+pub const INTERPOLATION_SMOOTH: u32 = 1;
+/// The colour of a stop up to the next one, in bands.
+pub const INTERPOLATION_STEP: u32 = 2;
+pub const INTERPOLATIONS: u32 = 3;
+
+/// sRGB colours as `#rrggbb` in files.
+mod hex {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(colour: &[f32; 3], serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b] = colour.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        serializer.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[f32; 3], D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let digits = text.strip_prefix('#').unwrap_or(&text);
+        let rgb = u32::from_str_radix(digits, 16)
+            .ok()
+            .filter(|_| digits.len() == 6)
+            .ok_or_else(|| D::Error::custom(format!("invalid colour {text}, expected #rrggbb")))?;
+        Ok([16, 8, 0].map(|shift| ((rgb >> shift) & 0xff) as f32 / 255.0))
+    }
+}
+
+/// Positions with at most 6 decimals in files, f32 has no more
+/// and would otherwise be written as the nearest f64.
+fn serialize_position<S: serde::Serializer>(position: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64((*position as f64 * 1e6).round() / 1e6)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    /// Where the stop is, from 0 to 1.
+    #[serde(serialize_with = "serialize_position")]
+    pub position: f32,
+    /// sRGB colour with components from 0 to 1.
+    #[serde(with = "hex")]
+    pub colour: [f32; 3],
+}
+
+/// A gradient that repeats every unit, after its last stop it blends into the first.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Palette {
+    #[serde(default)]
+    pub name: String,
+    stops: Vec<Stop>,
+}
+
+/// Built-in palettes as names and stops of a position and a `0xrrggbb` colour.
+const BUILTIN: &[(&str, &[(f32, u32)])] = &[
+    // what the colouring used to be before palettes
+    ("Classic", &[(0.0, 0x3a0000), (0.0404, 0x45000a), (0.7713, 0xffbac5), (1.0, 0xfff5ff)]),
+    ("Fire", &[(0.0, 0x000000), (0.25, 0x7a0000), (0.5, 0xff5a00), (0.75, 0xffd000), (0.9, 0xffffff)]),
+    ("Ocean", &[(0.0, 0x000764), (0.16, 0x206bcb), (0.42, 0xedffff), (0.6425, 0xffaa00), (0.8575, 0x000200)]),
+    ("Grey", &[(0.0, 0x000000), (0.5, 0xffffff)]),
+    ("Rainbow", &[
+        (0.0, 0xff0000), (1.0 / 6.0, 0xffff00), (2.0 / 6.0, 0x00ff00),
+        (3.0 / 6.0, 0x00ffff), (4.0 / 6.0, 0x0000ff), (5.0 / 6.0, 0xff00ff),
+    ]),
+];
+
+fn rgb(colour: u32) -> [f32; 3] {
+    [16, 8, 0].map(|shift| ((colour >> shift) & 0xff) as f32 / 255.0)
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], s: f32) -> [f32; 3] {
+    [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * s)
+}
+
+impl Palette {
+    /// A palette through `stops`, which may come in any order.
+    /// Palettes with more than [`MAX_STOPS`] stops are resampled.
+    pub fn new(name: String, mut stops: Vec<Stop>) -> Result<Self, String> {
+        if stops.is_empty() {
+            return Err("a palette needs at least one colour stop".to_string());
+        }
+        for stop in &mut stops {
+            if !stop.position.is_finite() || stop.colour.iter().any(|c| !c.is_finite()) {
+                return Err("colour stops must be finite".to_string());
+            }
+            stop.position = stop.position.clamp(0.0, 1.0);
+        }
+        stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+
+        let palette = Palette { name, stops };
+        if palette.stops.len() > MAX_STOPS {
+            Ok(palette.resample(MAX_STOPS))
+        }
+        else {
+            Ok(palette)
+        }
+    }
+
+    /// The built-in palettes, the first is the default.
+    pub fn builtin() -> Vec<Palette> {
+        BUILTIN.iter()
+            .map(|(name, stops)| Palette {
+                name: name.to_string(),
+                stops: stops.iter()
+                    .map(|&(position, colour)| Stop { position, colour: rgb(colour) })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn stops(&self) -> &[Stop] {
+        &self.stops
+    }
+
+    /// The colour at `t` with linear interpolation, like `palette` in the shaders.
+    pub fn colour_at(&self, t: f32) -> [f32; 3] {
+        let t = t - t.floor();
+        let n = self.stops.len();
+        let k = self.stops.partition_point(|stop| stop.position <= t);
+
+        let (a, a_position) = match k {
+            0 => (self.stops[n - 1], self.stops[n - 1].position - 1.0),
+            k => (self.stops[k - 1], self.stops[k - 1].position),
+        };
+        let (b, b_position) = match k {
+            k if k == n => (self.stops[0], self.stops[0].position + 1.0),
+            k => (self.stops[k], self.stops[k].position),
+        };
+
+        let s = if b_position > a_position { (t - a_position) / (b_position - a_position) } else { 0.0 };
+        mix(a.colour, b.colour, s)
+    }
+
+    /// `n` evenly spaced stops with the colours of the palette.
+    fn resample(&self, n: usize) -> Palette {
+        let stops = (0..n)
+            .map(|k| {
+                let position = k as f32 / n as f32;
+                Stop { position, colour: self.colour_at(position) }
+            })
+            .collect();
+        Palette { name: self.name.clone(), stops }
+    }
+
+    /// The texels of the palette texture, unused ones are zero.
+    pub fn texels(&self) -> Vec<[f32; 4]> {
+        let mut texels = vec![[0.0; 4]; MAX_STOPS];
+        for (texel, stop) in texels.iter_mut().zip(&self.stops) {
+            let [r, g, b] = stop.colour;
+            *texel = [r, g, b, stop.position];
+        }
+        texels
+    }
+
+    /// Reads a palette, in the format its extension says.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = extension(path)?;
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let name = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+
+        let palette = match extension.as_str() {
+            "map" => Self::from_map(name.clone(), &text),
+            "ggr" => Self::from_ggr(&text),
+            "json" => serde_json::from_str::<Palette>(&text).map_err(|e| e.to_string()),
+            "toml" => toml::from_str::<Palette>(&text).map_err(|e| e.to_string()),
+            _ => unreachable!(),
+        };
+        // the deserialized stops are not checked yet
+        let palette = palette.map_err(|e| format!("{}: {e}", path.display()))?;
+        Self::new(if palette.name.is_empty() { name } else { palette.name }, palette.stops)
+    }
+
+    /// Writes the palette, in the format the extension of `path` says.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let text = match extension(path)?.as_str() {
+            "map" => self.to_map(),
+            "ggr" => self.to_ggr(),
+            "json" => serde_json::to_string_pretty(self).map_err(|e| e.to_string())?,
+            "toml" => toml::to_string(self).map_err(|e| e.to_string())?,
+            _ => unreachable!(),
+        };
+        fs::write(path, text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Fractint palettes have 256 lines of `r g b` from 0 to 255,
+    /// anything after the three numbers is a comment.
+    fn from_map(name: String, text: &str) -> Result<Self, String> {
+        let colours = text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(k, line)| {
+                let numbers = line.split_whitespace()
+                    .take(3)
+                    .map(|word| word.parse::<u8>().map(|c| c as f32 / 255.0))
+                    .collect::<Result<Vec<_>, _>>();
+                match numbers {
+                    Ok(numbers) if numbers.len() == 3 => Ok([numbers[0], numbers[1], numbers[2]]),
+                    _ => Err(format!("line {}: expected r g b from 0 to 255", k + 1)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let n = colours.len() as f32;
+        let stops = colours.into_iter()
+            .enumerate()
+            .map(|(k, colour)| Stop { position: k as f32 / n, colour })
+            .collect();
+        Self::new(name, stops)
+    }
+
+    fn to_map(&self) -> String {
+        let mut text = String::new();
+        for stop in self.resample(256).stops {
+            let [r, g, b] = stop.colour.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+            let _ = writeln!(text, "{r} {g} {b}");
+        }
+        text
+    }
+
+    /// GIMP gradients are segments between a left and a right colour, blended
+    /// with one of several functions, which is approximated with extra stops
+    /// where it is not linear. HSV blending is done in RGB.
+    fn from_ggr(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines();
+        if lines.next().map(str::trim) != Some("GIMP Gradient") {
+            return Err("not a GIMP gradient".to_string());
+        }
+        let mut line = lines.next().unwrap_or_default();
+        let name = match line.strip_prefix("Name:") {
+            Some(name) => {
+                let name = name.trim().to_string();
+                line = lines.next().unwrap_or_default();
+                name
+            }
+            None => String::new(),
+        };
+        let count: usize = line.trim().parse().map_err(|_| "expected the number of segments".to_string())?;
+
+        let mut stops = Vec::new();
+        for k in 0..count {
+            let numbers = lines.next()
+                .ok_or_else(|| format!("expected {count} segments"))?
+                .split_whitespace()
+                .map(str::parse::<f32>)
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|numbers| numbers.len() >= 13)
+                .ok_or_else(|| format!("segment {}: expected at least 13 numbers", k + 1))?;
+
+            let [left, middle, right] = [numbers[0], numbers[1], numbers[2]];
+            let (c0, c1) = ([numbers[3], numbers[4], numbers[5]], [numbers[7], numbers[8], numbers[9]]);
+            let width = right - left;
+            let m = if width > 0.0 { ((middle - left) / width).clamp(1e-6, 1.0 - 1e-6) } else { 0.5 };
+
+            match numbers[11] as u32 {
+                // linear, exactly three stops
+                0 => stops.extend([
+                    Stop { position: left, colour: c0 },
+                    Stop { position: middle, colour: mix(c0, c1, 0.5) },
+                    Stop { position: right, colour: c1 },
+                ]),
+                // step
+                5 => stops.extend([
+                    Stop { position: left, colour: c0 },
+                    Stop { position: middle, colour: c0 },
+                    Stop { position: middle, colour: c1 },
+                    Stop { position: right, colour: c1 },
+                ]),
+                blend => {
+                    const SAMPLES: usize = 16;
+                    stops.extend((0..=SAMPLES).map(|j| {
+                        let x = j as f32 / SAMPLES as f32;
+                        let linear = if x <= m { 0.5 * x / m } else { 0.5 + 0.5 * (x - m) / (1.0 - m) };
+                        let f = match blend {
+                            // curved
+                            1 => x.powf(0.5f32.ln() / m.ln()),
+                            // sine
+                            2 => ((std::f32::consts::PI * (linear - 0.5)).sin() + 1.0) / 2.0,
+                            // sphere increasing and decreasing
+                            3 => (1.0 - (linear - 1.0) * (linear - 1.0)).sqrt(),
+                            4 => 1.0 - (1.0 - linear * linear).sqrt(),
+                            _ => linear,
+                        };
+                        Stop { position: left + x * width, colour: mix(c0, c1, f) }
+                    }));
+                }
+            }
+        }
+
+        Self::new(name, stops)
+    }
+
+    fn to_ggr(&self) -> String {
+        // the palette from 0 to 1, including where it wraps around
+        let mut points = Vec::with_capacity(self.stops.len() + 2);
+        if self.stops[0].position > 0.0 {
+            points.push(Stop { position: 0.0, colour: self.colour_at(0.0) });
+        }
+        points.extend(&self.stops);
+        if points[points.len() - 1].position < 1.0 {
+            points.push(Stop { position: 1.0, colour: self.colour_at(0.0) });
+        }
+
+        let segments: Vec<_> = points.windows(2)
+            .filter(|pair| pair[1].position > pair[0].position)
+            .collect();
+        let mut text = format!("GIMP Gradient\nName: {}\n{}\n", self.name, segments.len());
+        for pair in segments {
+            let (a, b) = (pair[0], pair[1]);
+            let [r0, g0, b0] = a.colour;
+            let [r1, g1, b1] = b.colour;
+            let _ = writeln!(text, "{:.6} {:.6} {:.6} {r0:.6} {g0:.6} {b0:.6} 1.000000 {r1:.6} {g1:.6} {b1:.6} 1.000000 0 0",
+                a.position, (a.position + b.position) / 2.0, b.position);
+        }
+        text
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::builtin().swap_remove(0)
+    }
+}
+
+fn extension(path: &Path) -> Result<String, String> {
+    let extension = path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "map" | "ggr" | "json" | "toml" => Ok(extension),
+        _ => Err(format!("{}: expected a .map, .ggr, .json or .toml file", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether the palettes have the same colours at `n` evenly spaced
+    /// positions, to within `tolerance`.
+    fn same_colours(a: &Palette, b: &Palette, n: usize, tolerance: f32) -> bool {
+        (0..n).all(|k| {
+            let t = k as f32 / n as f32;
+            a.colour_at(t).iter().zip(b.colour_at(t)).all(|(a, b)| (a - b).abs() <= tolerance)
+        })
+    }
+
+    /// Saves `palette` with `extension` and loads it back.
+    fn round_trip(palette: &Palette, extension: &str) -> Palette {
+        let path = std::env::temp_dir().join(format!("mandelbrot-palette-{}.{extension}", std::process::id()));
+        palette.save(&path).unwrap();
+        let loaded = Palette::load(&path);
+        let _ = fs::remove_file(&path);
+        loaded.unwrap()
+    }
+
+    fn sunset() -> Palette {
+        Palette::new("Sunset".to_string(), vec![
+            Stop { position: 0.1, colour: rgb(0x1a0533) },
+            Stop { position: 0.6, colour: rgb(0xff7b00) },
+            Stop { position: 0.8, colour: rgb(0xffffff) },
+        ]).unwrap()
+    }
+
+    #[test]
+    fn json_and_toml_round_trip() {
+        for palette in [sunset(), Palette::default()] {
+            for extension in ["json", "toml"] {
+                let loaded = round_trip(&palette, extension);
+                assert_eq!(loaded.name, palette.name);
+                assert_eq!(loaded.stops(), palette.stops(), "{extension}");
+            }
+        }
+    }
+
+    #[test]
+    fn ggr_round_trips() {
+        for palette in [sunset(), Palette::default()] {
+            let loaded = round_trip(&palette, "ggr");
+            assert_eq!(loaded.name, palette.name);
+            assert!(same_colours(&loaded, &palette, 1000, 1e-5));
+        }
+    }
+
+    #[test]
+    fn map_round_trips() {
+        // 256 evenly spaced colours of 8 bits each
+        for palette in [sunset(), Palette::default()] {
+            assert!(same_colours(&round_trip(&palette, "map"), &palette, 256, 0.5 / 255.0 + 1e-6));
+        }
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        assert!(Palette::from_map(String::new(), "0 0 0\n255 255\n").is_err());
+        assert!(Palette::from_map(String::new(), "0 0 256\n").is_err());
+        assert!(Palette::from_ggr("GIMP Gradient\nName: broken\n2\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err());
+        assert!(Palette::from_ggr("not a gradient\n").is_err());
+        assert!(serde_json::from_str::<Palette>(r##"{"stops": [{"position": 0, "colour": "#12345"}]}"##).is_err());
+        assert!(Palette::new(String::new(), Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+//! Reference orbits for rendering with perturbation theory.
+//!
+//! Past the precision of f64 every pixel's `c` is written as `C + dc`, where
+//! `C` is a single reference point. The orbit `Z` of `C` is computed here with
+//! arbitrary precision, and the shaders only iterate the small difference
+//! `dz = z - Z`, which fits into f32 or f64 even when `c` itself does not:
+//!
+//! `dz' = 2 * Z * dz + dz^2 + dc`
+//!
+//! For `z^d + c` with any integer `d` it is `dz' = (Z + dz)^d - Z^d + dc`,
+//! and the absolute values of the other formulas are taken of `Z + dz` and `Z`
+//! so that their difference is exact.
+//!
+//! For the first iterations `dz` is well described by a polynomial in `dc`,
+//! so the shaders can start from [`SeriesApproximation`] instead of zero.
+
+use crate::{fixed::Fixed, formula::{FOLD_ABS_X, FOLD_ABS_Y, FOLD_CONJUGATE, FOLD_ABS_RE}};
+
+type Complex = [f64; 2];
+
+fn add(a: Complex, b: Complex) -> Complex {
+    [a[0] + b[0], a[1] + b[1]]
+}
+
+fn mul(a: Complex, b: Complex) -> Complex {
+    [a[0]*b[0] - a[1]*b[1], a[0]*b[1] + a[1]*b[0]]
+}
+
+fn abs2(a: Complex) -> f64 {
+    a[0] * a[0] + a[1] * a[1]
+}
+
+/// Number of fractional bits needed to resolve pixels at `zoom`.
+pub fn precision(zoom: f64) -> u32 {
+    64 + (-zoom.log2()).max(0.0) as u32
+}
+
+/// `(x + iy)^exponent` by repeated squaring, for `exponent >= 1`.
+fn power(x: &Fixed, y: &Fixed, exponent: u32) -> (Fixed, Fixed) {
+    let mut base = (x.clone(), y.clone());
+    let mut result: Option<(Fixed, Fixed)> = None;
+    let mut exponent = exponent;
+
+    loop {
+        if exponent & 1 == 1 {
+            result = Some(match result {
+                Some((rx, ry)) => (
+                    &(&rx * &base.0) - &(&ry * &base.1),
+                    &(&rx * &base.1) + &(&ry * &base.0)),
+                None => base.clone(),
+            });
+        }
+        exponent >>= 1;
+        if exponent == 0 {
+            return result.expect("exponent must be positive");
+        }
+
+        let (bx, by) = &base;
+        let xy = bx * by;
+        base = (&(bx * bx) - &(by * by), &xy + &xy);
+    }
+}
+
+pub struct ReferenceOrbit {
+    points: Vec<[f64; 2]>,
+    exponent: u32,
+    fold: u32,
+}
+
+impl ReferenceOrbit {
+    /// Iterates `z^exponent + c` with the folds of a formula from zero at `center`,
+    /// until the orbit escapes or reaches `max_iter` iterations, at the precision of `center`.
+    pub fn new(center: &[Fixed; 2], max_iter: u32, exponent: u32, fold: u32) -> Self {
+        let [cx, cy] = center;
+        let mut x = Fixed::zero(cx.frac_bits());
+        let mut y = Fixed::zero(cx.frac_bits());
+
+        let mut points = Vec::with_capacity(max_iter as usize + 1);
+        points.push([0.0, 0.0]);
+
+        for _ in 0..max_iter {
+            if fold & FOLD_ABS_X != 0 {
+                x = x.abs();
+            }
+            if fold & FOLD_ABS_Y != 0 {
+                y = y.abs();
+            }
+            if fold & FOLD_CONJUGATE != 0 {
+                y = -&y;
+            }
+            let (mut px, py) = power(&x, &y, exponent);
+            if fold & FOLD_ABS_RE != 0 {
+                px = px.abs();
+            }
+            x = &px + cx;
+            y = &py + cy;
+
+            let point = [x.to_f64(), y.to_f64()];
+            points.push(point);
+
+            if point[0] * point[0] + point[1] * point[1] >= 4.0 {
+                break;
+            }
+        }
+
+        ReferenceOrbit { points, exponent, fold }
+    }
+
+    /// The orbit at `center + center_lo`, computed with enough precision for `zoom`.
+    pub fn at(center: [f64; 2], center_lo: [f64; 2], zoom: f64, max_iter: u32, exponent: u32, fold: u32) -> Self {
+        let bits = precision(zoom);
+        let center = [0, 1].map(|i| {
+            &Fixed::from_f64(center[i], bits) + &Fixed::from_f64(center_lo[i], bits)
+        });
+        Self::new(&center, max_iter, exponent, fold)
+    }
+
+    pub fn points(&self) -> &[[f64; 2]] {
+        &self.points
+    }
+
+    pub fn points32(&self) -> Vec<[f32; 2]> {
+        self.points.iter()
+            .map(|[x, y]| [*x as f32, *y as f32])
+            .collect()
+    }
+}
+
+/// Number of terms of the series approximation, must match `SERIES_TERMS` in the shaders.
+pub const SERIES_TERMS: usize = 8;
+
+/// Largest relative error of the series for deltas iterated with f32 and f64,
+/// well within the precision of either.
+pub const SERIES_TOLERANCE32: f64 = 1e-6;
+pub const SERIES_TOLERANCE64: f64 = 1e-12;
+
+/// Approximation of `dz` after `skip` iterations as `zoom * sum(a[k] * u^(k+1))`,
+/// where `u = dc / zoom` is the offset of the pixel in screen units.
+///
+/// The coefficients `a[k] = A[k] * zoom^k` are those of the series in `dc`
+/// scaled by the zoom. `a[0]` is the derivative of the orbit by `c`, the higher
+/// terms shrink like `zoom^k` and those that underflow are small enough to drop.
+pub struct SeriesApproximation {
+    coefficients: [Complex; SERIES_TERMS],
+    skip: u32,
+}
+
+impl SeriesApproximation {
+    /// Advances the series along `orbit` for as long as it matches the
+    /// perturbed orbits of probe points on the border of the view within
+    /// `tolerance`, relative to `dz`.
+    ///
+    /// `extent` is the largest offset `u` of a pixel from the center.
+    /// Only orbits of `z^2 + c` are approximated, for other formulas nothing is skipped.
+    pub fn new(orbit: &ReferenceOrbit, zoom: f64, extent: [f64; 2], tolerance: f64) -> Self {
+        let [ex, ey] = extent;
+        let probes = [
+            [-ex, -ey], [0.0, -ey], [ex, -ey],
+            [-ex, 0.0],             [ex, 0.0],
+            [-ex,  ey], [0.0,  ey], [ex,  ey],
+        ];
+        // dz / zoom of the probes, which unlike dz itself does not underflow
+        let mut w = [[0.0; 2]; 8];
+
+        let mut coefficients = [[0.0; 2]; SERIES_TERMS];
+        let mut series = SeriesApproximation { coefficients, skip: 0 };
+        if orbit.exponent != 2 || orbit.fold != 0 {
+            return series;
+        }
+
+        // leave the last point of the orbit to the shader
+        for (n, &z) in orbit.points.iter().enumerate().take(orbit.points.len().saturating_sub(2)) {
+            let z2 = [2.0 * z[0], 2.0 * z[1]];
+
+            // a[k]' = 2 * Z * a[k] + zoom * sum(a[i] * a[k - 1 - i]), plus 1 for a[0]
+            let mut next = [[0.0; 2]; SERIES_TERMS];
+            for k in 0..SERIES_TERMS {
+                let mut square = [0.0; 2];
+                for i in 0..k {
+                    square = add(square, mul(coefficients[i], coefficients[k - 1 - i]));
+                }
+                next[k] = add(mul(z2, coefficients[k]), [square[0] * zoom, square[1] * zoom]);
+            }
+            next[0][0] += 1.0;
+            coefficients = next;
+
+            let z = orbit.points[n + 1];
+            for (u, w) in probes.iter().zip(w.iter_mut()) {
+                // w' = (2 * Z + zoom * w) * w + u
+                *w = add(mul(add(z2, [w[0] * zoom, w[1] * zoom]), *w), *u);
+                let dz = [w[0] * zoom, w[1] * zoom];
+
+                // stop where the probes escape or would need rebasing
+                let abs = abs2(add(z, dz));
+                if abs >= 4.0 || abs < abs2(dz) {
+                    return series;
+                }
+
+                let error = abs2(add(Self::evaluate(&coefficients, *u), [-w[0], -w[1]]));
+                if error.is_nan() || error > tolerance * tolerance * abs2(*w) {
+                    return series;
+                }
+            }
+
+            series = SeriesApproximation { coefficients, skip: n as u32 + 1 };
+        }
+
+        series
+    }
+
+    /// `dz / zoom` for the pixel `u`.
+    fn evaluate(coefficients: &[Complex; SERIES_TERMS], u: Complex) -> Complex {
+        let mut sum = coefficients[SERIES_TERMS - 1];
+        for k in (0..SERIES_TERMS - 1).rev() {
+            sum = add(mul(sum, u), coefficients[k]);
+        }
+        mul(sum, u)
+    }
+
+    /// Number of iterations the series skips.
+    pub fn skip(&self) -> u32 {
+        self.skip
+    }
+
+    pub fn coefficients(&self) -> &[[f64; 2]] {
+        &self.coefficients
+    }
+
+    pub fn coefficients32(&self) -> Vec<[f32; 2]> {
+        self.coefficients.iter()
+            .map(|[x, y]| [*x as f32, *y as f32])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `c = i` is preperiodic, its orbit stays bounded at any depth.
+    const CENTER: [f64; 2] = [0.0, 1.0];
+
+    /// `dz / zoom` after `n` iterations for the pixel `u`, iterated from zero
+    /// with the terms of `dz' = 2 * Z * dz + dz^2 + dc` divided by the zoom.
+    fn perturbed(orbit: &ReferenceOrbit, zoom: f64, u: Complex, n: u32) -> Complex {
+        let mut w = [0.0; 2];
+        for &z in &orbit.points[..n as usize] {
+            let square = mul(w, w);
+            w = add(add(mul([2.0 * z[0], 2.0 * z[1]], w), [square[0] * zoom, square[1] * zoom]), u);
+        }
+        w
+    }
+
+    #[test]
+    fn series_skips_at_deep_zooms() {
+        for (zoom, tolerance) in [(1e-34, SERIES_TOLERANCE32), (1e-100, SERIES_TOLERANCE64), (1e-300, SERIES_TOLERANCE64)] {
+            let orbit = ReferenceOrbit::at(CENTER, [0.0; 2], zoom, 1000, 2, 0);
+            let series = SeriesApproximation::new(&orbit, zoom, [1.5, 1.0], tolerance);
+            assert!(series.skip() > 10, "zoom {zoom:e}: skips {}", series.skip());
+        }
+    }
+
+    #[test]
+    fn series_matches_perturbation() {
+        // the deltas are f64 down to 1e-300 and f32 down to 1e-34,
+        // the f32 coefficients as uploaded for the shaders
+        let cases = [
+            (1e-10, SERIES_TOLERANCE64, false), (1e-100, SERIES_TOLERANCE64, false), (1e-300, SERIES_TOLERANCE64, false),
+            (1e-10, SERIES_TOLERANCE32, true), (1e-34, SERIES_TOLERANCE32, true),
+        ];
+        for (zoom, tolerance, f32) in cases {
+            let orbit = ReferenceOrbit::at(CENTER, [0.0; 2], zoom, 1000, 2, 0);
+            let series = SeriesApproximation::new(&orbit, zoom, [1.5, 1.0], tolerance);
+            let coefficients = if f32 { series.coefficients.map(|a| a.map(|x| x as f32 as f64)) } else { series.coefficients };
+            for u in [[1.5, 1.0], [-1.5, 0.0], [0.0, -1.0], [0.5, 0.25], [-1.0, 0.75]] {
+                let w = perturbed(&orbit, zoom, u, series.skip());
+                let error = abs2(add(SeriesApproximation::evaluate(&coefficients, u), [-w[0], -w[1]]));
+                assert!(error <= tolerance * tolerance * abs2(w), "zoom {zoom:e}, u {u:?}");
+            }
+        }
+    }
+}
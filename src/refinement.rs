@@ -0,0 +1,163 @@
+//! Progressive refinement.
+//!
+//! Every change of the view starts the refinement over. Its first frame is a
+//! preview at a fraction of the resolution and of the iterations, quick enough to
+//! follow the mouse even at deep zoom. Points that escape later are black in it,
+//! the others keep their colours. Once the view has been left alone for a moment,
+//! the next frame renders it at full resolution and iterations with the samples
+//! of the settings. With jittered sampling the frames after it add that many
+//! samples more each, placed anew, to the accumulation texture, which
+//! `fs_display` shows the mean of. The other samplings would place them the same,
+//! their image is refined after that one frame.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    Properties,
+    offscreen::Offscreen,
+    interactive::camera_controller::{COLOURING_ITERATIONS, COLOURING_SMOOTH, COLOURING_TRAP_ITERATION, SAMPLING_GRID, SAMPLING_JITTERED}};
+
+/// Sum of the linear colours of the frames in rgb and their number in alpha.
+/// Unlike 32-bit floats, 16-bit floats can be blended.
+pub const ACCUMULATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Adds the colour of a frame to those of the frames before it.
+pub const ACCUMULATE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+/// Pixels of the window per pixel of the preview, along each side.
+const PREVIEW_SCALE: u32 = 4;
+/// Iterations of the refined image per iteration of the preview,
+/// past those the series approximation skips.
+const PREVIEW_ITERATIONS: u32 = 4;
+/// Frames at full resolution the refined image is the mean of, with jittered sampling.
+const REFINEMENT_FRAMES: u32 = 8;
+/// How long the view has to be left alone before it is refined,
+/// so that a pause while dragging does not hold up the next preview.
+const REFINEMENT_DELAY: Duration = Duration::from_millis(100);
+
+pub struct Refinement {
+    /// Frames rendered since the last change, the first is the preview.
+    step: u32,
+    changed: Instant,
+    /// Pixels of the window per pixel of the image in the accumulation texture.
+    scale: u32,
+    /// The accumulation texture, for `fs_display` in group 1.
+    accumulation: Offscreen,
+}
+
+impl Refinement {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Refinement {
+            step: 0,
+            changed: Instant::now(),
+            scale: PREVIEW_SCALE,
+            accumulation: Offscreen::new(device, "Accumulation texture", ACCUMULATION_FORMAT, width, height),
+        }
+    }
+
+    /// Recreates the accumulation texture for a window of `width`x`height`
+    /// and starts over.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.accumulation.resize(device, width, height);
+        self.restart();
+    }
+
+    /// Starts over with a preview, after the view has changed.
+    pub fn restart(&mut self) {
+        self.step = 0;
+        self.changed = Instant::now();
+    }
+
+    /// Whether the next frame is the preview, which follows every change.
+    pub fn preview(&self) -> bool {
+        self.step == 0
+    }
+
+    /// When the next frame is due, `None` once the image is refined.
+    pub fn next_frame(&self) -> Option<Instant> {
+        match self.step {
+            0 => Some(self.changed),
+            step if step <= REFINEMENT_FRAMES => Some(self.changed + REFINEMENT_DELAY),
+            _ => None,
+        }
+    }
+
+    /// Whether the next frame is due.
+    pub fn due(&self) -> bool {
+        self.next_frame().is_some_and(|next_frame| next_frame <= Instant::now())
+    }
+
+    /// Properties to render the next frame with, in linear colours for the
+    /// accumulation texture, `None` until it is due and once the image is refined.
+    pub fn frame(&self, properties: Properties) -> Option<Properties> {
+        if !self.due() {
+            return None;
+        }
+        let mut frame = properties;
+        frame.encode_srgb = 0;
+        match self.step {
+            0 => {
+                let (width, height) = properties.size();
+                frame.resize(width.div_ceil(PREVIEW_SCALE), height.div_ceil(PREVIEW_SCALE));
+                frame.samples = 1;
+                frame.sampling = SAMPLING_GRID;
+
+                let skip = properties.series_skip;
+                frame.max_iter = skip + properties.max_iter.saturating_sub(skip).div_ceil(PREVIEW_ITERATIONS);
+                // the palette spreads over max_iter for these
+                if matches!(properties.colouring, COLOURING_ITERATIONS | COLOURING_SMOOTH | COLOURING_TRAP_ITERATION) {
+                    frame.palette_scale *= frame.max_iter as f32 / properties.max_iter as f32;
+                }
+            }
+            1 => {}
+            step if step <= REFINEMENT_FRAMES => frame.seed = step,
+            _ => return None,
+        }
+        Some(frame)
+    }
+
+    /// Whether the next frame replaces the image instead of adding to it.
+    pub fn clear(&self) -> bool {
+        self.step <= 1
+    }
+
+    /// Properties for `fs_display` to show the image with.
+    pub fn display(&self, properties: Properties) -> Properties {
+        let mut display = properties;
+        display.pixel_scale = self.scale;
+        display
+    }
+
+    /// Moves on to the frame after the one just rendered with `sampling`.
+    /// While the palette cycles, the colours change from frame to frame and are
+    /// not averaged, every frame after the preview is rendered at full resolution anew.
+    pub fn advance(&mut self, sampling: u32, cycling: bool) {
+        self.scale = if self.preview() { PREVIEW_SCALE } else { 1 };
+        self.step =
+            if cycling { 1 }
+            else if !self.preview() && sampling != SAMPLING_JITTERED { REFINEMENT_FRAMES + 1 }
+            else { self.step + 1 };
+    }
+
+    /// Where the frames are rendered to.
+    pub fn accumulation_view(&self) -> &wgpu::TextureView {
+        self.accumulation.view()
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        self.accumulation.layout()
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        self.accumulation.bind_group()
+    }
+}
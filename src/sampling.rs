@@ -0,0 +1,41 @@
+//! GPU resources of adaptive sampling.
+//!
+//! With `SAMPLING_ADAPTIVE` the fractal shader first renders a sample at the
+//! center of every pixel into a sample texture. `fs_adaptive` then compares
+//! every pixel there with its neighbours and supersamples only those that differ
+//! strongly, the edges of the bands and the filaments of the set, while the
+//! smooth areas between them keep their single sample.
+
+use crate::offscreen::Offscreen;
+
+/// The colour of the sample at the center of the pixel.
+pub const SAMPLE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+pub struct Adaptive {
+    /// The sample texture, for `fs_adaptive` in group 1.
+    samples: Offscreen,
+}
+
+impl Adaptive {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        Adaptive { samples: Offscreen::new(device, "Sample texture", SAMPLE_FORMAT, width, height) }
+    }
+
+    /// Recreates the sample texture for a window of `width`x`height`.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.samples.resize(device, width, height);
+    }
+
+    /// Where the fractal shader writes the samples at the centers of the pixels to.
+    pub fn sample_view(&self) -> &wgpu::TextureView {
+        self.samples.view()
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        self.samples.layout()
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        self.samples.bind_group()
+    }
+}
@@ -0,0 +1,796 @@
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+}
+
+fn index_to_pos(index: u32) -> vec2<f32> {
+    switch index {
+        case 0u: { return vec2<f32>(-1.0, -1.0); }
+        case 1u: { return vec2<f32>(-1.0,  1.0); }
+        case 2u: { return vec2<f32>( 1.0, -1.0); }
+        case 3u: { return vec2<f32>(-1.0,  1.0); }
+        case 4u: { return vec2<f32>( 1.0, -1.0); }
+        default: { return vec2<f32>( 1.0,  1.0); }
+    }
+}
+
+fn index_to_tex(index: u32) -> vec2<f32> {
+    let aspect = properties.width / properties.height;
+
+    var x_diff = 0.0;
+    var y_diff = 0.0;
+    if aspect > 1.0 {
+        x_diff = (aspect - 1.0) / 2.0;
+    }
+    else if aspect < 1.0 {
+        y_diff = (1.0 / aspect - 1.0) / 2.0;
+    }
+
+    // add padding depending on properties.aspect ratio
+    let x0 = 0.0 - x_diff;
+    let x1 = 1.0 + x_diff;
+    let y0 = 0.0 - y_diff;
+    let y1 = 1.0 + y_diff;
+
+    switch index {
+        case 0u: { return vec2<f32>(x0, y0); }
+        case 1u: { return vec2<f32>(x0, y1); }
+        case 2u: { return vec2<f32>(x1, y0); }
+        case 3u: { return vec2<f32>(x0, y1); }
+        case 4u: { return vec2<f32>(x1, y0); }
+        default: { return vec2<f32>(x1, y1); }
+    }
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(index_to_pos(in.index), 0.0, 1.0);
+    out.tex_coords    = index_to_tex(in.index);
+    return out;
+}
+
+type Complex32 = vec2<f32>;
+
+fn mul32(a: Complex32, b: Complex32) -> Complex32 {
+    return Complex32(a.x*b.x - a.y*b.y, a.x*b.y + a.y*b.x);
+}
+
+fn div32(a: Complex32, b: Complex32) -> Complex32 {
+    return mul32(a, Complex32(b.x, -b.y)) / dot(b, b);
+}
+
+// z^e, by repeated multiplication for integer e and in polar form otherwise
+fn pow32(z: Complex32, e: f32) -> Complex32 {
+    if e == floor(e) {
+        var p = Complex32(1.0, 0.0);
+        for (var k = 0u; k < u32(e); k++) {
+            p = mul32(p, z);
+        }
+        return p;
+    }
+    let r = pow(dot(z, z), 0.5 * e);
+    let theta = atan2(z.y, z.x) * e;
+    return r * Complex32(cos(theta), sin(theta));
+}
+
+// elementary functions for custom formulas, log and sqrt on their principal branch
+fn exp32(z: Complex32) -> Complex32 {
+    return exp(z.x) * Complex32(cos(z.y), sin(z.y));
+}
+
+fn log32(z: Complex32) -> Complex32 {
+    return Complex32(0.5 * log(dot(z, z)), atan2(z.y, z.x));
+}
+
+fn sqrt32(z: Complex32) -> Complex32 {
+    return pow32(z, 0.5);
+}
+
+fn sin32(z: Complex32) -> Complex32 {
+    return Complex32(sin(z.x) * cosh(z.y), cos(z.x) * sinh(z.y));
+}
+
+fn cos32(z: Complex32) -> Complex32 {
+    return Complex32(cos(z.x) * cosh(z.y), -sin(z.x) * sinh(z.y));
+}
+
+fn sinh32(z: Complex32) -> Complex32 {
+    return Complex32(sinh(z.x) * cos(z.y), cosh(z.x) * sin(z.y));
+}
+
+fn cosh32(z: Complex32) -> Complex32 {
+    return Complex32(cosh(z.x) * cos(z.y), sinh(z.x) * sin(z.y));
+}
+
+// z -> f(z, c) of the formula typed by the user and der -> the derivative of that,
+// given the derivative dc of c, the body is generated by expression.rs
+fn custom_step32(z: ptr<function, Complex32>, der: ptr<function, Complex32>, c: Complex32, dc: Complex32) {
+    let w = *z;
+    let dw = *der;
+//CUSTOM_FORMULA
+}
+
+// bits of properties.fold, see formula.rs
+const FOLD_ABS_X = 1u;
+const FOLD_ABS_Y = 2u;
+const FOLD_CONJUGATE = 4u;
+const FOLD_ABS_RE = 8u;
+
+// the folds of the formula before the power
+fn fold32(z: Complex32) -> Complex32 {
+    var z = z;
+    if (properties.fold & FOLD_ABS_X) != 0u { z.x = abs(z.x); }
+    if (properties.fold & FOLD_ABS_Y) != 0u { z.y = abs(z.y); }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { z.y = -z.y; }
+    return z;
+}
+
+// the folds are reflections, which flip the derivative der at z with them
+fn fold_derivative32(z: Complex32, der: Complex32) -> Complex32 {
+    var der = der;
+    if (properties.fold & FOLD_ABS_X) != 0u && z.x < 0.0 { der.x = -der.x; }
+    if (properties.fold & FOLD_ABS_Y) != 0u && z.y < 0.0 { der.y = -der.y; }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { der.y = -der.y; }
+    return der;
+}
+
+// z -> fold(z)^d with the absolute value of the real part if the formula takes it,
+// and der -> the derivative of that times der
+fn step32(z: ptr<function, Complex32>, der: ptr<function, Complex32>) {
+    let w = fold32(*z);
+    let p = pow32(w, properties.exponent - 1.0);
+    *der = properties.exponent * mul32(p, fold_derivative32(*z, *der));
+    *z = mul32(p, w);
+    if (properties.fold & FOLD_ABS_RE) != 0u && (*z).x < 0.0 {
+        (*z).x = -(*z).x;
+        (*der).x = -(*der).x;
+    }
+}
+
+fn hsv2rgb(c: vec3<f32>) -> vec3<f32> {
+    let K = vec4<f32>(1.0, 2.0 / 3.0, 1.0 / 3.0, 3.0);
+    let p = abs(fract(c.xxx + K.xyz) * 6.0 - K.www);
+    let clamped = p - K.xxx;
+    return c.z * mix(K.xxx, clamped, c.y);
+}
+
+// Colours are computed, lit and averaged in linear space. The palette, the grey
+// of the distance colouring and the hues of the Newton fractal are meant as they
+// are shown, in sRGB, and are decoded where they come from. The colours are
+// encoded back when written to the window, by the surface itself if its format
+// is sRGB and by output otherwise.
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+// the colour to write to the window
+fn output(colour: vec3<f32>) -> vec4<f32> {
+    if properties.encode_srgb != 0u {
+        return vec4<f32>(linear_to_srgb(colour), 1.0);
+    }
+    return vec4<f32>(colour, 1.0);
+}
+
+// values of properties.colouring
+const COLOURING_ITERATIONS = 0u;
+const COLOURING_SMOOTH = 1u;
+const COLOURING_DISTANCE = 2u;
+const COLOURING_HISTOGRAM = 3u;
+const COLOURING_TRAP_DISTANCE = 4u;
+const COLOURING_TRAP_ITERATION = 5u;
+const COLOURING_STRIPE = 6u;
+const COLOURING_TRIANGLE = 7u;
+const COLOURING_CURVATURE = 8u;
+
+// the stops of the palette, a texel each with the colour in rgb and the position in a,
+// see palette.rs
+@group(0) @binding(4)
+var palette_texture: texture_1d<f32>;
+
+// values of properties.palette_interpolation
+const INTERPOLATION_LINEAR = 0u;
+const INTERPOLATION_SMOOTH = 1u;
+const INTERPOLATION_STEP = 2u;
+
+// colour of the palette at t, which repeats every unit
+fn palette(t: f32) -> vec3<f32> {
+    let t = fract(t);
+    let n = i32(properties.palette_stops);
+
+    // the last stop at or before t
+    var k = -1;
+    var low = 0;
+    var high = n - 1;
+    while low <= high {
+        let middle = (low + high) / 2;
+        if textureLoad(palette_texture, middle, 0).a <= t {
+            k = middle;
+            low = middle + 1;
+        }
+        else {
+            high = middle - 1;
+        }
+    }
+
+    // before the first stop and after the last one it blends from the last into the first
+    var a = textureLoad(palette_texture, (k + n) % n, 0);
+    var b = textureLoad(palette_texture, (k + 1) % n, 0);
+    if k < 0 {
+        a.a -= 1.0;
+    }
+    if k == n - 1 {
+        b.a += 1.0;
+    }
+
+    var s = 0.0;
+    if b.a > a.a {
+        s = (t - a.a) / (b.a - a.a);
+    }
+    if properties.palette_interpolation == INTERPOLATION_SMOOTH {
+        s = smoothstep(0.0, 1.0, s);
+    }
+    else if properties.palette_interpolation == INTERPOLATION_STEP {
+        s = 0.0;
+    }
+    // blended as the gradient is drawn, in sRGB
+    return srgb_to_linear(mix(a.rgb, b.rgb, s));
+}
+
+// colour of the palette for iteration count iter, as a fraction of max_iter,
+// shifted by how far the palette has cycled
+fn palette_colour(iter: f32) -> vec3<f32> {
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    return palette(offset + properties.palette_scale * iter / f32(properties.max_iter));
+}
+
+// values of properties.trap
+const TRAP_POINT = 0u;
+const TRAP_LINE = 1u;
+const TRAP_CROSS = 2u;
+const TRAP_CIRCLE = 3u;
+const TRAP_STALK = 4u;
+
+// how close an orbit came to the trap
+struct Trap {
+    // the smallest distance of its points to the trap
+    distance: f32,
+    // the first iteration within trap_width of the trap, 0 if none, and the distance then
+    hit: u32,
+    hit_distance: f32,
+}
+
+fn new_trap() -> Trap {
+    return Trap(1e30, 0u, 0.0);
+}
+
+fn trap_colouring() -> bool {
+    return properties.colouring == COLOURING_TRAP_DISTANCE || properties.colouring == COLOURING_TRAP_ITERATION;
+}
+
+fn trap_distance(z: Complex32) -> f32 {
+    let d = z - properties.trap_center;
+    let direction = vec2<f32>(cos(properties.trap_angle), sin(properties.trap_angle));
+    // distances along the line and across it
+    let along = abs(dot(d, direction));
+    let across = abs(d.x * direction.y - d.y * direction.x);
+    if properties.trap == TRAP_LINE {
+        return across;
+    }
+    else if properties.trap == TRAP_CROSS {
+        return min(across, along);
+    }
+    else if properties.trap == TRAP_CIRCLE {
+        return abs(length(d) - properties.trap_radius);
+    }
+    else if properties.trap == TRAP_STALK {
+        return min(abs(d.x), abs(d.y));
+    }
+    return length(d);
+}
+
+// adds the point z of an orbit after n iterations to the trap
+fn update_trap(trap: ptr<function, Trap>, z: Complex32, n: u32) {
+    if !trap_colouring() {
+        return;
+    }
+    let distance = trap_distance(z);
+    (*trap).distance = min((*trap).distance, distance);
+    if (*trap).hit == 0u && distance < properties.trap_width {
+        (*trap).hit = n;
+        (*trap).hit_distance = distance;
+    }
+}
+
+// colour of a point inside the set, only the trap colourings have one
+fn inside_colour(trap: Trap) -> vec3<f32> {
+    if properties.colouring == COLOURING_TRAP_DISTANCE {
+        return trap_colour(trap);
+    }
+    return vec3<f32>(0.0);
+}
+
+// the distance fades out over the width of the trap,
+// a hit is between its iteration and the next one by how close it was
+fn trap_colour(trap: Trap) -> vec3<f32> {
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    if properties.colouring == COLOURING_TRAP_DISTANCE {
+        return palette(offset + properties.palette_scale * min(trap.distance / properties.trap_width, 1.0));
+    }
+    return palette_colour(f32(trap.hit) + trap.hit_distance / properties.trap_width);
+}
+
+// The averaging colourings add up a term for every point of the orbit and colour
+// by their mean. The mean jumps where the orbit escapes an iteration earlier,
+// so it is blended with the mean without the last term by the continuous
+// iteration count, as smooth_iter blends n - 1 and n.
+
+const PI = 3.14159265;
+
+struct Average {
+    sum: f32,
+    // the last term added and the number of them
+    last: f32,
+    count: u32,
+    // the two points of the orbit before z
+    z1: Complex32,
+    z2: Complex32,
+}
+
+fn new_average(z: Complex32) -> Average {
+    return Average(0.0, 0.0, 0u, z, z);
+}
+
+fn average_colouring() -> bool {
+    return properties.colouring == COLOURING_STRIPE
+        || properties.colouring == COLOURING_TRIANGLE
+        || properties.colouring == COLOURING_CURVATURE;
+}
+
+// adds the term for z, the point after z1, of the orbit of z^d + c
+fn update_average(average: ptr<function, Average>, z: Complex32, c: Complex32) {
+    if !average_colouring() {
+        return;
+    }
+    var term = 0.0;
+    var valid = true;
+    if properties.colouring == COLOURING_STRIPE {
+        // stripes along the angle of z
+        term = 0.5 + 0.5 * sin(properties.stripe_density * atan2(z.y, z.x));
+    }
+    else if properties.colouring == COLOURING_TRIANGLE {
+        // where |z| lies between the bounds the triangle inequality puts on |z1^d + c|
+        let power = pow(length((*average).z1), properties.exponent);
+        let lowest = abs(power - length(c));
+        let highest = power + length(c);
+        valid = highest > lowest;
+        term = (length(z) - lowest) / (highest - lowest);
+    }
+    else {
+        // how sharply the orbit turns at z1
+        let a = z - (*average).z1;
+        let b = (*average).z1 - (*average).z2;
+        let cross = b.x * a.y - b.y * a.x;
+        let along = dot(a, b);
+        valid = cross != 0.0 || along != 0.0;
+        term = abs(atan2(cross, along)) / PI;
+    }
+    (*average).z2 = (*average).z1;
+    (*average).z1 = z;
+    if valid {
+        (*average).sum += term;
+        (*average).last = term;
+        (*average).count++;
+    }
+}
+
+fn average_colour(average: Average, abs: f32, n: u32) -> vec3<f32> {
+    var mean = average.sum;
+    if average.count > 1u {
+        let previous = (average.sum - average.last) / f32(average.count - 1u);
+        let t = clamp(smooth_iter(abs, n) - f32(n), 0.0, 1.0);
+        mean = mix(previous, average.sum / f32(average.count), t);
+    }
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    return palette(offset + properties.palette_scale * mean);
+}
+
+// continuous iteration count: |z| grows from the bailout radius r to r^d
+// during the last iteration, which interpolates between n - 1 and n
+fn smooth_iter(abs: f32, n: u32) -> f32 {
+    return f32(n) + 1.0 - log(log(abs) / log(properties.bailout)) / log(properties.exponent);
+}
+
+// exterior distance estimate from |z| and |dz/du| after escaping, in screen units
+fn distance_estimate(abs: f32, der: f32) -> f32 {
+    return abs * log(abs) / der;
+}
+
+// colour of a point that escaped after n iterations with |z| = abs,
+// at the estimated distance from the set and with its orbit caught in trap
+fn colour(abs: f32, n: u32, distance: f32, trap: Trap) -> vec3<f32> {
+    if properties.colouring == COLOURING_TRAP_DISTANCE
+        || (properties.colouring == COLOURING_TRAP_ITERATION && trap.hit != 0u) {
+        return trap_colour(trap);
+    }
+    else if properties.colouring == COLOURING_DISTANCE {
+        // the boundary is black and fades to white within a pixel,
+        // whatever the zoom or the resolution
+        let pixels = distance * 0.5 * min(properties.width, properties.height);
+        return srgb_to_linear(vec3<f32>(clamp(sqrt(pixels), 0.0, 1.0)));
+    }
+    else if properties.colouring == COLOURING_SMOOTH {
+        return palette_colour(smooth_iter(abs, n));
+    }
+    else if properties.colouring == COLOURING_HISTOGRAM {
+        // only the count for now, with 1 in g to tell it from the black inside the set
+        return vec3<f32>(max(smooth_iter(abs, n), 0.0), 1.0, 0.0);
+    }
+    else if properties.colouring == COLOURING_TRAP_ITERATION || average_colouring() {
+        // orbits that miss the trap are coloured as usual, under it, and
+        // the loops colour the averages of their orbits before they get here
+        return palette_colour(smooth_iter(abs, n));
+    }
+    else {
+        return palette_colour(f32(n));
+    }
+}
+
+// Lighting shades the escaped points as if the potential, which grows away from
+// the set, were the height of a landscape lit from light_angle and light_height.
+// The normal of the landscape points along z / (dz/dc).
+
+// the fraction of the colour that is not in the shade
+const AMBIENT = 0.2;
+const SHININESS = 20.0;
+
+fn light(rgb: vec3<f32>, z: Complex32, der: Complex32) -> vec3<f32> {
+    // the histogram colouring has no colour yet, only the count
+    if properties.lighting == 0u || properties.colouring == COLOURING_HISTOGRAM {
+        return rgb;
+    }
+    // the direction of z / der, from the directions of both so that it cannot overflow
+    let a = normalize(z);
+    let b = normalize(der);
+    let u = Complex32(a.x * b.x + a.y * b.y, a.y * b.x - a.x * b.y);
+    let normal = normalize(vec3<f32>(u, 1.0));
+    let to_light = normalize(vec3<f32>(cos(properties.light_angle), sin(properties.light_angle), properties.light_height));
+    let diffuse = max(dot(normal, to_light), 0.0);
+    // Blinn-Phong, seen from straight above
+    let halfway = normalize(to_light + vec3<f32>(0.0, 0.0, 1.0));
+    let specular = properties.specular * pow(max(dot(normal, halfway), 0.0), SHININESS);
+    return rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse) + vec3<f32>(specular);
+}
+
+// Histogram colouring takes two passes. fs_main writes the iteration counts
+// into the iteration texture instead of colours, histogram.wgsl sorts them into
+// bins between the smallest and the largest count, and fs_histogram colours
+// every pixel by the fraction of the escaped pixels that took fewer iterations.
+
+const HISTOGRAM_BINS = 4096u;
+
+struct Histogram {
+    // bits of the smallest count, inverted, and of the largest one
+    min_inverted: u32,
+    max: u32,
+    // cumulative, the number of counts in this bin or a lower one
+    counts: array<u32, HISTOGRAM_BINS>,
+}
+
+// the mean count of the escaped samples of a pixel in r, times the fraction of them in g
+@group(1) @binding(0)
+var iteration_texture: texture_2d<f32>;
+
+@group(1) @binding(1)
+var<storage, read> histogram: Histogram;
+
+// fraction of the escaped pixels with fewer than iter iterations,
+// interpolated within the bin of iter
+fn histogram_cdf(iter: f32) -> f32 {
+    let lowest = bitcast<f32>(~histogram.min_inverted);
+    let highest = bitcast<f32>(histogram.max);
+    let bin = (iter - lowest) / max(highest - lowest, 1e-6) * f32(HISTOGRAM_BINS);
+    let k = min(u32(max(bin, 0.0)), HISTOGRAM_BINS - 1u);
+
+    var below = 0u;
+    if k > 0u {
+        below = histogram.counts[k - 1u];
+    }
+    let within = f32(histogram.counts[k] - below) * clamp(bin - f32(k), 0.0, 1.0);
+    return (f32(below) + within) / f32(max(histogram.counts[HISTOGRAM_BINS - 1u], 1u));
+}
+
+@fragment
+fn fs_histogram(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureLoad(iteration_texture, vec2<i32>(vertex.clip_position.xy), 0);
+    if texel.g == 0.0 {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+
+    let t = histogram_cdf(texel.r / texel.g);
+    let offset = properties.palette_offset + properties.palette_speed * properties.time;
+    // samples inside the set are black
+    return output(palette(offset + properties.palette_scale * t) * texel.g);
+}
+
+// whether to test for the main cardioid and period 2 bulb, they are only known for z^2 + c,
+// and the trap colourings need the orbits inside them
+fn check_cardioid_or_bulb() -> bool {
+    return properties.interior_checks != 0u && properties.fold == 0u && properties.exponent == 2.0
+        && properties.custom == 0u && !trap_colouring();
+}
+
+// main cardioid and period 2 bulb, every c in them is inside the set
+fn in_cardioid_or_bulb32(c: Complex32) -> bool {
+    let x = c.x - 0.25;
+    let y2 = c.y * c.y;
+    let q = x * x + y2;
+    let x1 = c.x + 1.0;
+    return q * (q + x) <= 0.25 * y2 || x1 * x1 + y2 <= 0.0625;
+}
+
+// two points of an orbit closer than this are the same, in units of zoom
+const PERIODICITY_EPSILON = 1e-4;
+
+// dz and dc are the derivatives of the starting z and of c
+// by the offset of the pixel from the center in screen units
+fn julia32(c: Complex32, z: Complex32, dz: Complex32, dc: f32) -> vec3<f32> {
+    var z = z;
+    var der = dz;
+    var n = 0u;
+    var abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    let epsilon = f32(properties.zoom) * PERIODICITY_EPSILON;
+    var saved = z;
+    var period = 0u;
+    var period_limit = 1u;
+    var trap = new_trap();
+    var average = new_average(z);
+
+    while (abs < bailout && n < properties.max_iter) {
+        if properties.custom != 0u {
+            custom_step32(&z, &der, c, Complex32(dc, 0.0));
+        }
+        else {
+            step32(&z, &der);
+            der += Complex32(dc, 0.0);
+            z += c;
+        }
+        abs = z.x * z.x + z.y * z.y;
+        n++;
+        update_trap(&trap, z, n);
+        update_average(&average, z, c);
+
+        // Brent's cycle detection: an orbit that returns to a saved point
+        // is periodic and never escapes, the point is saved again after
+        // twice as many iterations as before
+        if properties.interior_checks != 0u {
+            let d = z - saved;
+            if dot(d, d) < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period++;
+            if period == period_limit {
+                saved = z;
+                period = 0u;
+                period_limit *= 2u;
+            }
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, abs, n), z, der);
+    }
+    else {
+        return light(colour(abs, n, distance_estimate(abs, length(der)), trap), z, der);
+    }
+}
+
+fn mandelbrot32(c: Complex32) -> vec3<f32> {
+    if check_cardioid_or_bulb() && in_cardioid_or_bulb32(c) {
+        return vec3<f32>(0.0);
+    }
+    // custom formulas start at z = c, which for z^2 + c skips an iteration,
+    // so that those like sin(z) * c do not stay at zero
+    if properties.custom != 0u {
+        let dc = f32(properties.zoom);
+        return julia32(c, c, Complex32(dc, 0.0), dc);
+    }
+    return julia32(c, Complex32(0.0, 0.0), Complex32(0.0, 0.0), f32(properties.zoom));
+}
+
+// polynomial of the Newton fractal, see newton.rs
+const MAX_DEGREE = 8u;
+
+struct Polynomial {
+    degree: u32,
+    // from the constant term up, MAX_DEGREE + 1 of them
+    coefficients: array<Complex32, 9>,
+    roots: array<Complex32, MAX_DEGREE>,
+}
+
+@group(0) @binding(3)
+var<storage, read> polynomial: Polynomial;
+
+// squared distance to a root within which Newton's method counts as converged
+const NEWTON_EPSILON = 1e-10;
+
+// the hue tells the root, the brightness falls with the iterations n it took
+// to get within the squared distance to it
+fn newton_colour(root: u32, n: u32, distance: f32) -> vec3<f32> {
+    var iter = f32(n);
+    if properties.colouring != COLOURING_ITERATIONS {
+        // near a simple root the distance squares with every iteration,
+        // log(distance) / log(epsilon) goes from 1 to 2 during the last one
+        let t = clamp(log(distance) / log(NEWTON_EPSILON), 1.0, 2.0);
+        iter += 1.0 - log2(t);
+    }
+    let hue = f32(root) / f32(polynomial.degree);
+    return srgb_to_linear(hsv2rgb(vec3<f32>(hue, 0.75, exp(-0.1 * iter))));
+}
+
+// Newton's method z -> z - p(z) / p'(z) from z, black if it reaches no root
+fn newton32(z: Complex32) -> vec3<f32> {
+    var z = z;
+    for (var n = 0u; n < properties.max_iter; n++) {
+        // p(z) and p'(z) by Horner's scheme
+        var p = polynomial.coefficients[polynomial.degree];
+        var dp = Complex32(0.0, 0.0);
+        for (var k = i32(polynomial.degree) - 1; k >= 0; k--) {
+            dp = mul32(dp, z) + p;
+            p = mul32(p, z) + polynomial.coefficients[k];
+        }
+        z -= div32(p, dp);
+
+        for (var r = 0u; r < polynomial.degree; r++) {
+            let d = z - polynomial.roots[r];
+            if dot(d, d) < NEWTON_EPSILON {
+                return newton_colour(r, n, dot(d, d));
+            }
+        }
+    }
+    return vec3<f32>(0.0);
+}
+
+// the Mandelbrot set at c = p, the Julia set for properties.julia_c at z = p,
+// or the Newton fractal at z = p
+fn fractal32(p: Complex32) -> vec3<f32> {
+    if properties.newton != 0u {
+        return newton32(p);
+    }
+    if properties.julia != 0u {
+        return julia32(Complex32(properties.julia_c), p, Complex32(f32(properties.zoom), 0.0), 0.0);
+    }
+    return mandelbrot32(p);
+}
+
+// Every pixel is the mean of properties.samples samples on a square grid over it.
+// Jittered sampling moves each of them to a random point of its cell of the grid,
+// and adaptive sampling only supersamples where neighbouring pixels differ.
+
+// values of properties.sampling
+const SAMPLING_GRID = 0u;
+const SAMPLING_JITTERED = 1u;
+const SAMPLING_ADAPTIVE = 2u;
+
+// PCG hash
+fn hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// uniformly distributed in [0, 1)
+fn random(seed: u32) -> f32 {
+    return f32(hash(seed) >> 8u) / 16777216.0;
+}
+
+// offset of sample i from the center of the pixel at position in the framebuffer,
+// in texture coordinates, on a grid of side x side samples
+fn sample_offset(position: vec2<f32>, i: u32, side: u32) -> vec2<f32> {
+    var cell = vec2<f32>(f32(i % side), f32(i / side)) + 0.5;
+    if properties.sampling == SAMPLING_JITTERED {
+        let pixel = vec2<u32>(position);
+        let seed = hash(pixel.x ^ hash(pixel.y ^ hash(i ^ hash(properties.seed))));
+        cell = vec2<f32>(f32(i % side) + random(seed), f32(i / side) + random(hash(seed)));
+    }
+    // the texture coordinates span the shorter side of the window from 0 to 1
+    let pixel_size = 1.0 / min(properties.width, properties.height);
+    return (cell / f32(side) - 0.5) * pixel_size;
+}
+
+fn supersample(vertex: VertexOutput) -> vec3<f32> {
+    let side = u32(round(sqrt(f32(properties.samples))));
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < side * side; i++) {
+        sum += colour_at(vertex.tex_coords.xy + sample_offset(vertex.clip_position.xy, i, side));
+    }
+    return sum / f32(side * side);
+}
+
+// whether fs_main writes iteration counts for fs_histogram,
+// the Newton fractal colours by the root, not the iteration count
+fn histogram_colouring() -> bool {
+    return properties.colouring == COLOURING_HISTOGRAM && properties.newton == 0u;
+}
+
+// whether fs_adaptive supersamples after fs_main, with histogram colouring
+// fs_main supersamples the iteration counts on the grid instead
+fn adaptive_sampling() -> bool {
+    return properties.sampling == SAMPLING_ADAPTIVE && !histogram_colouring();
+}
+
+@fragment
+fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    if adaptive_sampling() {
+        // the sample at the center, for fs_adaptive to compare with the neighbours
+        return vec4<f32>(colour_at(vertex.tex_coords.xy), 1.0);
+    }
+    let colour = supersample(vertex);
+    if histogram_colouring() {
+        return vec4<f32>(colour, 1.0);
+    }
+    return output(colour);
+}
+
+// Adaptive sampling takes two passes. fs_main takes a sample at the center of
+// every pixel into the sample texture, and fs_adaptive supersamples the pixels
+// that differ from one of their eight neighbours there by more than the threshold
+// in a channel, the others keep their sample.
+
+const ADAPTIVE_THRESHOLD = 0.1;
+
+@group(1) @binding(0)
+var sample_texture: texture_2d<f32>;
+
+@fragment
+fn fs_adaptive(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(vertex.clip_position.xy);
+    let last = vec2<i32>(textureDimensions(sample_texture)) - 1;
+    let texel = textureLoad(sample_texture, pixel, 0).rgb;
+
+    var difference = 0.0;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let neighbour = clamp(pixel + vec2<i32>(dx, dy), vec2<i32>(0), last);
+            let d = abs(textureLoad(sample_texture, neighbour, 0).rgb - texel);
+            difference = max(difference, max(d.r, max(d.g, d.b)));
+        }
+    }
+
+    if difference > ADAPTIVE_THRESHOLD {
+        return output(supersample(vertex));
+    }
+    return output(texel);
+}
+
+// Progressive refinement renders the frames of a view into the accumulation
+// texture, adding up their linear colours in rgb and their number in alpha,
+// see refinement.rs. fs_display shows the mean. A preview of lower resolution
+// fills only the top left of the texture, a texel per pixel_scale x pixel_scale
+// pixels of the window.
+
+@group(1) @binding(0)
+var accumulation_texture: texture_2d<f32>;
+
+@fragment
+fn fs_display(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(vertex.clip_position.xy) / i32(properties.pixel_scale);
+    let texel = textureLoad(accumulation_texture, pixel, 0);
+    return output(texel.rgb / max(texel.a, 1.0));
+}
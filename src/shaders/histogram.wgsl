@@ -0,0 +1,89 @@
+// Compute passes of histogram colouring, see fs_histogram in common.wgsl.
+// The histogram buffer starts zeroed, the passes run in the order
+// range, count and accumulate, each over the whole iteration texture.
+
+const HISTOGRAM_BINS = 4096u;
+// invocations of accumulate, each takes HISTOGRAM_BINS / ACCUMULATE_SIZE bins
+const ACCUMULATE_SIZE = 256u;
+
+struct Histogram {
+    // the smallest count is the largest of the inverted bits,
+    // so that zero is the start of both
+    min_inverted: atomic<u32>,
+    max: atomic<u32>,
+    counts: array<atomic<u32>, HISTOGRAM_BINS>,
+}
+
+@group(0) @binding(0)
+var iteration_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> histogram: Histogram;
+
+// mean iteration count of the escaped samples of the pixel at id, negative if none escaped
+fn load_iter(id: vec2<u32>) -> f32 {
+    let size = vec2<u32>(textureDimensions(iteration_texture));
+    if id.x >= size.x || id.y >= size.y {
+        return -1.0;
+    }
+    let texel = textureLoad(iteration_texture, vec2<i32>(id), 0);
+    if texel.g == 0.0 {
+        return -1.0;
+    }
+    return texel.r / texel.g;
+}
+
+@compute @workgroup_size(16, 16)
+fn range(@builtin(global_invocation_id) id: vec3<u32>) {
+    let iter = load_iter(id.xy);
+    if iter >= 0.0 {
+        // non-negative floats order like their bits
+        let bits = bitcast<u32>(iter);
+        atomicMax(&histogram.min_inverted, ~bits);
+        atomicMax(&histogram.max, bits);
+    }
+}
+
+@compute @workgroup_size(16, 16)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+    let iter = load_iter(id.xy);
+    if iter >= 0.0 {
+        let lowest = bitcast<f32>(~atomicLoad(&histogram.min_inverted));
+        let highest = bitcast<f32>(atomicLoad(&histogram.max));
+        let bin = (iter - lowest) / max(highest - lowest, 1e-6) * f32(HISTOGRAM_BINS);
+        atomicAdd(&histogram.counts[min(u32(bin), HISTOGRAM_BINS - 1u)], 1u);
+    }
+}
+
+var<workgroup> sums: array<u32, ACCUMULATE_SIZE>;
+
+// turns the counts into cumulative counts, in a single workgroup
+@compute @workgroup_size(256)
+fn accumulate(@builtin(local_invocation_index) i: u32) {
+    let bins = HISTOGRAM_BINS / ACCUMULATE_SIZE;
+    let first = i * bins;
+
+    var sum = 0u;
+    for (var k = first; k < first + bins; k++) {
+        sum += atomicLoad(&histogram.counts[k]);
+    }
+    sums[i] = sum;
+    workgroupBarrier();
+
+    // sums of the bins of this invocation and all before it, in log2(ACCUMULATE_SIZE) steps
+    for (var step = 1u; step < ACCUMULATE_SIZE; step *= 2u) {
+        var value = sums[i];
+        if i >= step {
+            value += sums[i - step];
+        }
+        workgroupBarrier();
+        sums[i] = value;
+        workgroupBarrier();
+    }
+
+    var total = sums[i] - sum;
+    for (var k = first; k < first + bins; k++) {
+        total += atomicLoad(&histogram.counts[k]);
+        atomicStore(&histogram.counts[k], total);
+    }
+}
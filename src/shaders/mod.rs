@@ -0,0 +1,39 @@
+//! WGSL sources of the fractal shaders.
+//!
+//! Each variant is assembled from `common.wgsl`, which holds the vertex stage,
+//! the colouring and the f32 iteration, and the parts for its precision.
+//! The code of the custom formula is spliced into `common.wgsl` in place of
+//! [`CUSTOM_FORMULA`].
+
+use crate::expression::Expression;
+
+const COMMON: &str = include_str!("common.wgsl");
+/// Uniforms and perturbation with f32 deltas.
+const SHADER32: &str = include_str!("shader32.wgsl");
+/// Double-single arithmetic for adapters without 64-bit floats.
+const SHADERDS: &str = include_str!("shaderds.wgsl");
+/// Uniforms, native 64-bit math and perturbation with f64 deltas.
+const SHADER64: &str = include_str!("shader64.wgsl");
+/// Double-double arithmetic on top of native f64.
+const SHADERDD: &str = include_str!("shaderdd.wgsl");
+/// Compute passes of histogram colouring, the same for both variants.
+pub const HISTOGRAM: &str = include_str!("histogram.wgsl");
+
+/// Line in the body of `custom_step32` that the custom formula replaces.
+const CUSTOM_FORMULA: &str = "//CUSTOM_FORMULA\n";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Emulates 64-bit precision with pairs of f32, works on every adapter.
+    DoubleSingle,
+    /// Uses native f64 and pairs of f64, needs `Features::SHADER_FLOAT64`.
+    Float64,
+}
+
+pub fn source(variant: Variant, custom: &Expression) -> String {
+    let parts: &[&str] = match variant {
+        Variant::DoubleSingle => &[COMMON, SHADER32, SHADERDS],
+        Variant::Float64 => &[COMMON, SHADER64, SHADERDD],
+    };
+    parts.join("\n").replacen(CUSTOM_FORMULA, &custom.wgsl(), 1)
+}
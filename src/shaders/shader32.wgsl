@@ -0,0 +1,189 @@
+struct Properties32 {
+    center: vec2<f32>,
+    // low parts of the center as double-single
+    center_lo: vec2<f32>,
+    zoom: f32,
+    width: f32, height: f32,
+    i_width: f32, i_height: f32,
+    math64: u32,
+    orbit_len: u32,
+    series_skip: u32,
+    max_iter: u32,
+    // escape radius
+    bailout: f32,
+    colouring: u32,
+    // cardioid, bulb and periodicity checks for points inside the set
+    interior_checks: u32,
+    // Julia set for julia_c instead of the Mandelbrot set
+    julia: u32,
+    // d in z^d + c
+    exponent: f32,
+    // absolute values and conjugation of the formula, see formula.rs
+    fold: u32,
+    // Newton fractal of the polynomial instead of an escape-time fractal
+    newton: u32,
+    julia_c: vec2<f32>,
+    // custom_step32 instead of the built-in formulas
+    custom: u32,
+    // where the palette starts and how often it repeats over max_iter iterations
+    palette_offset: f32,
+    palette_scale: f32,
+    palette_interpolation: u32,
+    palette_stops: u32,
+    time: f32,
+    palette_speed: f32,
+    // shape of the orbit trap, see trap.rs, and its parameters
+    trap: u32,
+    trap_center: vec2<f32>,
+    trap_angle: f32,
+    trap_radius: f32,
+    trap_width: f32,
+    // how many stripes the stripe average colouring puts around the set
+    stripe_density: f32,
+    // nonzero to shade escaped points by the light, from light_angle in the plane
+    // and light_height above it, with highlights of strength specular
+    lighting: u32,
+    light_angle: f32,
+    light_height: f32,
+    specular: f32,
+    // samples per pixel, a square number, and how they are placed
+    samples: u32,
+    sampling: u32,
+    // nonzero when the render target stores colours as they are, see output
+    encode_srgb: u32,
+    // varies the placement of the jittered samples from frame to frame
+    seed: u32,
+    // pixels of the window per texel of the accumulated image, see fs_display
+    pixel_scale: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> properties: Properties32;
+
+// reference orbit for perturbation, computed on the CPU
+@group(0) @binding(1)
+var<storage, read> orbit: array<Complex32>;
+
+// coefficients of the series approximation of the perturbation
+@group(0) @binding(2)
+var<storage, read> series: array<Complex32, SERIES_TERMS>;
+
+const SERIES_TERMS = 8u;
+
+// dz after series_skip iterations, for a pixel u from the center in screen units,
+// the coefficients are scaled by the zoom
+fn series32(u: Complex32) -> Complex32 {
+    var sum = series[SERIES_TERMS - 1u];
+    for (var k = i32(SERIES_TERMS) - 2; k >= 0; k--) {
+        sum = mul32(sum, u) + series[k];
+    }
+    return mul32(sum, u) * properties.zoom;
+}
+
+// derivative of series32 by u
+fn series32_derivative(u: Complex32) -> Complex32 {
+    var sum = series[SERIES_TERMS - 1u];
+    var der = Complex32(0.0, 0.0);
+    for (var k = i32(SERIES_TERMS) - 2; k >= 0; k--) {
+        der = mul32(der, u) + sum;
+        sum = mul32(sum, u) + series[k];
+    }
+    return (mul32(der, u) + sum) * properties.zoom;
+}
+
+// (Z + dz)^d - Z^d for integer d, as dz * sum(z^k * Z^(d-1-k)) with z = Z + dz,
+// which does not lose dz to cancellation like the difference itself would
+fn power_difference32(Z: Complex32, dz: Complex32) -> Complex32 {
+    let z = Z + dz;
+    var sum = Complex32(1.0, 0.0);
+    var power = Complex32(1.0, 0.0);
+    for (var k = 1u; k < u32(properties.exponent); k++) {
+        power = mul32(power, Z);
+        sum = mul32(sum, z) + power;
+    }
+    return mul32(sum, dz);
+}
+
+// |a + d| - |a| without cancellation
+fn diffabs32(a: f32, d: f32) -> f32 {
+    if a >= 0.0 {
+        if a + d >= 0.0 { return d; }
+        return -(2.0 * a + d);
+    }
+    if a + d > 0.0 { return 2.0 * a + d; }
+    return -d;
+}
+
+// fold32(Z + dz) - fold32(Z)
+fn fold_difference32(Z: Complex32, dz: Complex32) -> Complex32 {
+    var d = dz;
+    if (properties.fold & FOLD_ABS_X) != 0u { d.x = diffabs32(Z.x, dz.x); }
+    if (properties.fold & FOLD_ABS_Y) != 0u { d.y = diffabs32(Z.y, dz.y); }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { d.y = -d.y; }
+    return d;
+}
+
+// step32 of z = Z + dz for the difference dz to the reference Z
+fn perturbation_step32(Z: Complex32, dz: ptr<function, Complex32>, der: ptr<function, Complex32>) {
+    let W = fold32(Z);
+    let dw = fold_difference32(Z, *dz);
+    let p = pow32(W + dw, properties.exponent - 1.0);
+    *der = properties.exponent * mul32(p, fold_derivative32(Z + *dz, *der));
+    *dz = power_difference32(W, dw);
+    if (properties.fold & FOLD_ABS_RE) != 0u {
+        let a = pow32(W, properties.exponent).x;
+        if a + (*dz).x < 0.0 { (*der).x = -(*der).x; }
+        (*dz).x = diffabs32(a, (*dz).x);
+    }
+}
+
+// iterates the difference dz of the orbit to the reference orbit, for c = C + u * zoom
+fn perturbation32(u: Complex32) -> vec3<f32> {
+    let dc = u * properties.zoom;
+    var dz = series32(u);
+    // derivative of z by u
+    var der = series32_derivative(u);
+    var m = properties.series_skip;
+    var n = properties.series_skip;
+    var abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    var trap = new_trap();
+    // the terms of the average for the iterations the series skips as in perturbation64
+    let c = properties.center + dc;
+    var average = new_average(orbit[0]);
+    for (var k = 1u; k <= m && average_colouring(); k++) {
+        update_average(&average, orbit[k], c);
+    }
+
+    while (abs < bailout && n < properties.max_iter) {
+        perturbation_step32(orbit[m], &dz, &der);
+        der += Complex32(properties.zoom, 0.0);
+        dz += dc;
+        m++;
+        let z = orbit[m] + dz;
+        abs = z.x * z.x + z.y * z.y;
+        n++;
+        update_trap(&trap, z, n);
+        update_average(&average, z, c);
+
+        // rebase to the start of the reference orbit when z gets closer
+        // to zero than to the reference or the reference has escaped
+        if (abs < dz.x * dz.x + dz.y * dz.y || m == properties.orbit_len - 1u) {
+            dz = z;
+            m = 0u;
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, abs, n), orbit[m] + dz, der);
+    }
+    else {
+        // z is orbit[m] + dz also after rebasing, orbit[0] is zero
+        return light(colour(abs, n, distance_estimate(abs, length(der)), trap), orbit[m] + dz, der);
+    }
+}
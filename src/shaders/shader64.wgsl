@@ -0,0 +1,338 @@
+struct Properties {
+    center: vec2<f64>,
+    // low parts of the center as double-double
+    center_lo: vec2<f64>,
+    zoom: f64,
+    width: f32, height: f32,
+    i_width: f32, i_height: f32,
+    math64: u32,
+    orbit_len: u32,
+    series_skip: u32,
+    max_iter: u32,
+    // escape radius
+    bailout: f32,
+    colouring: u32,
+    // cardioid, bulb and periodicity checks for points inside the set
+    interior_checks: u32,
+    // Julia set for julia_c instead of the Mandelbrot set
+    julia: u32,
+    // d in z^d + c
+    exponent: f32,
+    // absolute values and conjugation of the formula, see formula.rs
+    fold: u32,
+    julia_c: vec2<f64>,
+    // Newton fractal of the polynomial instead of an escape-time fractal
+    newton: u32,
+    // custom_step32 instead of the built-in formulas
+    custom: u32,
+    // where the palette starts and how often it repeats over max_iter iterations
+    palette_offset: f32,
+    palette_scale: f32,
+    palette_interpolation: u32,
+    palette_stops: u32,
+    time: f32,
+    palette_speed: f32,
+    // shape of the orbit trap, see trap.rs, and its parameters
+    trap: u32,
+    trap_angle: f32,
+    trap_center: vec2<f32>,
+    trap_radius: f32,
+    trap_width: f32,
+    // how many stripes the stripe average colouring puts around the set
+    stripe_density: f32,
+    // nonzero to shade escaped points by the light, from light_angle in the plane
+    // and light_height above it, with highlights of strength specular
+    lighting: u32,
+    light_angle: f32,
+    light_height: f32,
+    specular: f32,
+    // samples per pixel, a square number, and how they are placed
+    samples: u32,
+    sampling: u32,
+    // nonzero when the render target stores colours as they are, see output
+    encode_srgb: u32,
+    // varies the placement of the jittered samples from frame to frame
+    seed: u32,
+    // pixels of the window per texel of the accumulated image, see fs_display
+    pixel_scale: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> properties: Properties;
+
+// reference orbit for perturbation, computed on the CPU
+@group(0) @binding(1)
+var<storage, read> orbit: array<Complex64>;
+
+// coefficients of the series approximation of the perturbation
+@group(0) @binding(2)
+var<storage, read> series: array<Complex64, SERIES_TERMS>;
+
+type Complex64 = vec2<f64>;
+
+fn mul64(a: Complex64, b: Complex64) -> Complex64 {
+    return Complex64(a.x*b.x - a.y*b.y, a.x*b.y + a.y*b.x);
+}
+
+// z^e for integer e only, there are no f64 transcendental functions
+// and real exponents are rendered with f32
+fn pow64(z: Complex64, e: u32) -> Complex64 {
+    var p = Complex64(f64(1), f64(0));
+    for (var k = 0u; k < e; k++) {
+        p = mul64(p, z);
+    }
+    return p;
+}
+
+fn fold64(z: Complex64) -> Complex64 {
+    var z = z;
+    if (properties.fold & FOLD_ABS_X) != 0u { z.x = abs(z.x); }
+    if (properties.fold & FOLD_ABS_Y) != 0u { z.y = abs(z.y); }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { z.y = -z.y; }
+    return z;
+}
+
+fn fold_derivative64(z: Complex64, der: Complex64) -> Complex64 {
+    var der = der;
+    if (properties.fold & FOLD_ABS_X) != 0u && z.x < f64(0) { der.x = -der.x; }
+    if (properties.fold & FOLD_ABS_Y) != 0u && z.y < f64(0) { der.y = -der.y; }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { der.y = -der.y; }
+    return der;
+}
+
+// step32 for integer exponents
+fn step64(z: ptr<function, Complex64>, der: ptr<function, Complex64>) {
+    let w = fold64(*z);
+    let p = pow64(w, u32(properties.exponent) - 1u);
+    *der = f64(properties.exponent) * mul64(p, fold_derivative64(*z, *der));
+    *z = mul64(p, w);
+    if (properties.fold & FOLD_ABS_RE) != 0u && (*z).x < f64(0) {
+        (*z).x = -(*z).x;
+        (*der).x = -(*der).x;
+    }
+}
+
+fn length64(a: Complex64) -> f64 {
+    return sqrt(a.x * a.x + a.y * a.y);
+}
+
+fn in_cardioid_or_bulb64(c: Complex64) -> bool {
+    let x = c.x - f64(0.25);
+    let y2 = c.y * c.y;
+    let q = x * x + y2;
+    let x1 = c.x + f64(1);
+    return q * (q + x) <= f64(0.25) * y2 || x1 * x1 + y2 <= f64(0.0625);
+}
+
+// the derivatives dz and dc are as in julia32, the exponent must be an integer
+fn julia64(c: Complex64, z: Complex64, dz: Complex64, dc: f64) -> vec3<f32> {
+    var z = z;
+    var der = dz;
+    var n = 0u;
+    var abs = f64(0);
+    let bailout = f64(properties.bailout * properties.bailout);
+    let epsilon = properties.zoom * f64(PERIODICITY_EPSILON);
+    var saved = z;
+    var period = 0u;
+    var period_limit = 1u;
+    var trap = new_trap();
+    var average = new_average(Complex32(z));
+
+    while (abs < bailout && n < properties.max_iter) {
+        step64(&z, &der);
+        der += Complex64(dc, f64(0));
+        z += c;
+        abs = z.x * z.x + z.y * z.y;
+        n++;
+        update_trap(&trap, Complex32(z), n);
+        update_average(&average, Complex32(z), Complex32(c));
+
+        // periodicity checking as in julia32
+        if properties.interior_checks != 0u {
+            let d = z - saved;
+            if d.x * d.x + d.y * d.y < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period++;
+            if period == period_limit {
+                saved = z;
+                period = 0u;
+                period_limit *= 2u;
+            }
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, f32(abs), n), Complex32(z), Complex32(der / length64(der)));
+    }
+    else {
+        return light(colour(f32(abs), n, distance_estimate(f32(abs), f32(length64(der))), trap),
+            Complex32(z), Complex32(der / length64(der)));
+    }
+}
+
+const SERIES_TERMS = 8u;
+
+// dz after series_skip iterations, for a pixel u from the center in screen units,
+// the coefficients are scaled by the zoom
+fn series64(u: Complex64) -> Complex64 {
+    var sum = series[SERIES_TERMS - 1u];
+    for (var k = i32(SERIES_TERMS) - 2; k >= 0; k--) {
+        sum = mul64(sum, u) + series[k];
+    }
+    return mul64(sum, u) * properties.zoom;
+}
+
+// derivative of series64 by u
+fn series64_derivative(u: Complex64) -> Complex64 {
+    var sum = series[SERIES_TERMS - 1u];
+    var der = Complex64(f64(0), f64(0));
+    for (var k = i32(SERIES_TERMS) - 2; k >= 0; k--) {
+        der = mul64(der, u) + sum;
+        sum = mul64(sum, u) + series[k];
+    }
+    return (mul64(der, u) + sum) * properties.zoom;
+}
+
+// (Z + dz)^d - Z^d for integer d, as dz * sum(z^k * Z^(d-1-k)) with z = Z + dz,
+// which does not lose dz to cancellation like the difference itself would
+fn power_difference64(Z: Complex64, dz: Complex64) -> Complex64 {
+    let z = Z + dz;
+    var sum = Complex64(f64(1), f64(0));
+    var power = Complex64(f64(1), f64(0));
+    for (var k = 1u; k < u32(properties.exponent); k++) {
+        power = mul64(power, Z);
+        sum = mul64(sum, z) + power;
+    }
+    return mul64(sum, dz);
+}
+
+// |a + d| - |a| without cancellation
+fn diffabs64(a: f64, d: f64) -> f64 {
+    if a >= f64(0) {
+        if a + d >= f64(0) { return d; }
+        return -(f64(2) * a + d);
+    }
+    if a + d > f64(0) { return f64(2) * a + d; }
+    return -d;
+}
+
+// fold64(Z + dz) - fold64(Z)
+fn fold_difference64(Z: Complex64, dz: Complex64) -> Complex64 {
+    var d = dz;
+    if (properties.fold & FOLD_ABS_X) != 0u { d.x = diffabs64(Z.x, dz.x); }
+    if (properties.fold & FOLD_ABS_Y) != 0u { d.y = diffabs64(Z.y, dz.y); }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { d.y = -d.y; }
+    return d;
+}
+
+// step64 of z = Z + dz for the difference dz to the reference Z
+fn perturbation_step64(Z: Complex64, dz: ptr<function, Complex64>, der: ptr<function, Complex64>) {
+    let W = fold64(Z);
+    let dw = fold_difference64(Z, *dz);
+    let p = pow64(W + dw, u32(properties.exponent) - 1u);
+    *der = f64(properties.exponent) * mul64(p, fold_derivative64(Z + *dz, *der));
+    *dz = power_difference64(W, dw);
+    if (properties.fold & FOLD_ABS_RE) != 0u {
+        let a = pow64(W, u32(properties.exponent)).x;
+        if a + (*dz).x < f64(0) { (*der).x = -(*der).x; }
+        (*dz).x = diffabs64(a, (*dz).x);
+    }
+}
+
+// iterates the difference dz of the orbit to the reference orbit, for c = C + u * zoom
+fn perturbation64(u: Complex64) -> vec3<f32> {
+    let dc = u * properties.zoom;
+    var dz = series64(u);
+    // derivative of z by u
+    var der = series64_derivative(u);
+    var m = properties.series_skip;
+    var n = properties.series_skip;
+    var abs = f64(0);
+    let bailout = f64(properties.bailout * properties.bailout);
+    var trap = new_trap();
+    // the iterations the series skips are those of the reference, to within
+    // its radius, and so are the terms of the average for them
+    let c = Complex32(properties.center + dc);
+    var average = new_average(Complex32(orbit[0]));
+    for (var k = 1u; k <= m && average_colouring(); k++) {
+        update_average(&average, Complex32(orbit[k]), c);
+    }
+
+    while (abs < bailout && n < properties.max_iter) {
+        perturbation_step64(orbit[m], &dz, &der);
+        der += Complex64(properties.zoom, f64(0));
+        dz += dc;
+        m++;
+        let z = orbit[m] + dz;
+        abs = z.x * z.x + z.y * z.y;
+        n++;
+        update_trap(&trap, Complex32(z), n);
+        update_average(&average, Complex32(z), c);
+
+        // rebase to the start of the reference orbit when z gets closer
+        // to zero than to the reference or the reference has escaped
+        if (abs < dz.x * dz.x + dz.y * dz.y || m == properties.orbit_len - 1u) {
+            dz = z;
+            m = 0u;
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, f32(abs), n), Complex32(orbit[m] + dz), Complex32(der / length64(der)));
+    }
+    else {
+        // z is orbit[m] + dz also after rebasing, orbit[0] is zero
+        return light(colour(f32(abs), n, distance_estimate(f32(abs), f32(length64(der))), trap),
+            Complex32(orbit[m] + dz), Complex32(der / length64(der)));
+    }
+}
+
+fn mandelbrot64(c: Complex64) -> vec3<f32> {
+    if check_cardioid_or_bulb() && in_cardioid_or_bulb64(c) {
+        return vec3<f32>(0.0);
+    }
+    return julia64(c, Complex64(f64(0), f64(0)), Complex64(f64(0), f64(0)), properties.zoom);
+}
+
+fn fractal64(p: Complex64) -> vec3<f32> {
+    if properties.julia != 0u {
+        return julia64(properties.julia_c, p, Complex64(properties.zoom, f64(0)), f64(0));
+    }
+    return mandelbrot64(p);
+}
+
+// colour at the point of the plane at tex_coords, with the math of properties.math64
+fn colour_at(tex_coords: vec2<f32>) -> vec3<f32> {
+    if properties.math64 == 2u {
+        let u = vec2<f64>(tex_coords * 2.0 - vec2<f32>(1.0, 1.0));
+        return perturbation64(u);
+    }
+    else if properties.math64 == 3u {
+        // the offset from the center is small enough for f64
+        let offset = vec2<f64>(tex_coords * 2.0 - vec2<f32>(1.0, 1.0)) * properties.zoom;
+        let c = ComplexDd(
+            add_dd(Dd(properties.center.x, properties.center_lo.x), Dd(offset.x, f64(0))),
+            add_dd(Dd(properties.center.y, properties.center_lo.y), Dd(offset.y, f64(0))));
+        return fractaldd(c);
+    }
+    else if properties.math64 != 0u {
+        let c = vec2<f64>(tex_coords * 2.0 - vec2<f32>(1.0, 1.0)) * properties.zoom + properties.center;
+        return fractal64(c);
+    }
+    else {
+        let c = (tex_coords * 2.0 - vec2<f32>(1.0, 1.0)) * f32(properties.zoom) + vec2<f32>(properties.center);
+        return fractal32(c);
+    }
+}
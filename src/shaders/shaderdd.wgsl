@@ -0,0 +1,174 @@
+
+
+// double-double arithmetic: a number is the unevaluated sum hi + lo of two f64,
+// which gives about 106 bits of mantissa
+type Dd = vec2<f64>;
+
+struct ComplexDd {
+    x: Dd,
+    y: Dd,
+}
+
+// a + b exactly, for |a| >= |b|
+fn quick_two_sum64(a: f64, b: f64) -> Dd {
+    let s = a + b;
+    return Dd(s, b - (s - a));
+}
+
+// a + b exactly
+fn two_sum64(a: f64, b: f64) -> Dd {
+    let s = a + b;
+    let v = s - a;
+    return Dd(s, (a - (s - v)) + (b - v));
+}
+
+// splits a into two halves of 26 bits each
+fn split64(a: f64) -> vec2<f64> {
+    let t = f64(134217729) * a;
+    let hi = t - (t - a);
+    return vec2<f64>(hi, a - hi);
+}
+
+// a * b exactly
+fn two_prod64(a: f64, b: f64) -> Dd {
+    let p = a * b;
+    let a_ = split64(a);
+    let b_ = split64(b);
+    let e = ((a_.x * b_.x - p) + a_.x * b_.y + a_.y * b_.x) + a_.y * b_.y;
+    return Dd(p, e);
+}
+
+fn add_dd(a: Dd, b: Dd) -> Dd {
+    var s = two_sum64(a.x, b.x);
+    let t = two_sum64(a.y, b.y);
+    s.y += t.x;
+    s = quick_two_sum64(s.x, s.y);
+    s.y += t.y;
+    return quick_two_sum64(s.x, s.y);
+}
+
+fn sub_dd(a: Dd, b: Dd) -> Dd {
+    return add_dd(a, -b);
+}
+
+fn mul_dd(a: Dd, b: Dd) -> Dd {
+    var p = two_prod64(a.x, b.x);
+    p.y += a.x * b.y + a.y * b.x;
+    return quick_two_sum64(p.x, p.y);
+}
+
+fn muldd(a: ComplexDd, b: ComplexDd) -> ComplexDd {
+    return ComplexDd(
+        sub_dd(mul_dd(a.x, b.x), mul_dd(a.y, b.y)),
+        add_dd(mul_dd(a.x, b.y), mul_dd(a.y, b.x)));
+}
+
+// z^e for integer e
+fn powdd(z: ComplexDd, e: u32) -> ComplexDd {
+    var p = ComplexDd(Dd(f64(1), f64(0)), Dd(f64(0), f64(0)));
+    for (var k = 0u; k < e; k++) {
+        p = muldd(p, z);
+    }
+    return p;
+}
+
+fn folddd(z: ComplexDd) -> ComplexDd {
+    var z = z;
+    if (properties.fold & FOLD_ABS_X) != 0u && z.x.x < f64(0) { z.x = -z.x; }
+    if (properties.fold & FOLD_ABS_Y) != 0u && z.y.x < f64(0) { z.y = -z.y; }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { z.y = -z.y; }
+    return z;
+}
+
+// step64 with double-double z, for integer exponents
+fn stepdd(z: ptr<function, ComplexDd>, der: ptr<function, Complex64>) {
+    let w = folddd(*z);
+    let p = powdd(w, u32(properties.exponent) - 1u);
+    let z_hi = Complex64((*z).x.x, (*z).y.x);
+    *der = f64(properties.exponent) * mul64(Complex64(p.x.x, p.y.x), fold_derivative64(z_hi, *der));
+    *z = muldd(p, w);
+    if (properties.fold & FOLD_ABS_RE) != 0u && (*z).x.x < f64(0) {
+        (*z).x = -(*z).x;
+        (*der).x = -(*der).x;
+    }
+}
+
+fn in_cardioid_or_bulbdd(c: ComplexDd) -> bool {
+    let x = sub_dd(c.x, Dd(f64(0.25), f64(0)));
+    let y2 = mul_dd(c.y, c.y);
+    let q = add_dd(mul_dd(x, x), y2);
+    let cardioid = sub_dd(mul_dd(q, add_dd(q, x)), f64(0.25) * y2);
+    let x1 = add_dd(c.x, Dd(f64(1), f64(0)));
+    let bulb = sub_dd(add_dd(mul_dd(x1, x1), y2), Dd(f64(0.0625), f64(0)));
+    return cardioid.x <= f64(0) || bulb.x <= f64(0);
+}
+
+// the derivatives dz and dc are as in julia32,
+// their precision only matters for the colour, the exponent must be an integer
+fn juliadd(c: ComplexDd, z: ComplexDd, dz: Complex64, dc: f64) -> vec3<f32> {
+    var z = z;
+    var der = dz;
+    var n = 0u;
+    var abs = f64(0);
+    let bailout = f64(properties.bailout * properties.bailout);
+    let epsilon = properties.zoom * f64(PERIODICITY_EPSILON);
+    var saved = z;
+    var period = 0u;
+    var period_limit = 1u;
+    var trap = new_trap();
+    var average = new_average(Complex32(f32(z.x.x), f32(z.y.x)));
+
+    while (abs < bailout && n < properties.max_iter) {
+        stepdd(&z, &der);
+        der += Complex64(dc, f64(0));
+        z.x = add_dd(z.x, c.x);
+        z.y = add_dd(z.y, c.y);
+        abs = z.x.x * z.x.x + z.y.x * z.y.x;
+        n++;
+        update_trap(&trap, Complex32(f32(z.x.x), f32(z.y.x)), n);
+        update_average(&average, Complex32(f32(z.x.x), f32(z.y.x)), Complex32(f32(c.x.x), f32(c.y.x)));
+
+        // periodicity checking as in julia32
+        if properties.interior_checks != 0u {
+            let d = Complex64(sub_dd(z.x, saved.x).x, sub_dd(z.y, saved.y).x);
+            if d.x * d.x + d.y * d.y < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period++;
+            if period == period_limit {
+                saved = z;
+                period = 0u;
+                period_limit *= 2u;
+            }
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, f32(abs), n), Complex32(f32(z.x.x), f32(z.y.x)), Complex32(der / length64(der)));
+    }
+    else {
+        return light(colour(f32(abs), n, distance_estimate(f32(abs), f32(length64(der))), trap),
+            Complex32(f32(z.x.x), f32(z.y.x)), Complex32(der / length64(der)));
+    }
+}
+
+fn mandelbrotdd(c: ComplexDd) -> vec3<f32> {
+    if check_cardioid_or_bulb() && in_cardioid_or_bulbdd(c) {
+        return vec3<f32>(0.0);
+    }
+    return juliadd(c, ComplexDd(Dd(f64(0), f64(0)), Dd(f64(0), f64(0))), Complex64(f64(0), f64(0)), properties.zoom);
+}
+
+fn fractaldd(p: ComplexDd) -> vec3<f32> {
+    if properties.julia != 0u {
+        let c = ComplexDd(Dd(properties.julia_c.x, f64(0)), Dd(properties.julia_c.y, f64(0)));
+        return juliadd(c, p, Complex64(properties.zoom, f64(0)), f64(0));
+    }
+    return mandelbrotdd(p);
+}
@@ -0,0 +1,193 @@
+
+// double-single arithmetic: a number is the unevaluated sum hi + lo of two f32,
+// which gives about 48 bits of mantissa without SHADER_FLOAT64
+type Ds = vec2<f32>;
+
+struct ComplexDs {
+    x: Ds,
+    y: Ds,
+}
+
+// a + b exactly, for |a| >= |b|
+fn quick_two_sum(a: f32, b: f32) -> Ds {
+    let s = a + b;
+    return Ds(s, b - (s - a));
+}
+
+// a + b exactly
+fn two_sum(a: f32, b: f32) -> Ds {
+    let s = a + b;
+    let v = s - a;
+    return Ds(s, (a - (s - v)) + (b - v));
+}
+
+// splits a into two halves of 12 bits each
+fn split(a: f32) -> vec2<f32> {
+    let t = 4097.0 * a;
+    let hi = t - (t - a);
+    return vec2<f32>(hi, a - hi);
+}
+
+// a * b exactly
+fn two_prod(a: f32, b: f32) -> Ds {
+    let p = a * b;
+    let a_ = split(a);
+    let b_ = split(b);
+    let e = ((a_.x * b_.x - p) + a_.x * b_.y + a_.y * b_.x) + a_.y * b_.y;
+    return Ds(p, e);
+}
+
+fn add_ds(a: Ds, b: Ds) -> Ds {
+    var s = two_sum(a.x, b.x);
+    let t = two_sum(a.y, b.y);
+    s.y += t.x;
+    s = quick_two_sum(s.x, s.y);
+    s.y += t.y;
+    return quick_two_sum(s.x, s.y);
+}
+
+fn sub_ds(a: Ds, b: Ds) -> Ds {
+    return add_ds(a, -b);
+}
+
+fn mul_ds(a: Ds, b: Ds) -> Ds {
+    var p = two_prod(a.x, b.x);
+    p.y += a.x * b.y + a.y * b.x;
+    return quick_two_sum(p.x, p.y);
+}
+
+fn mulds(a: ComplexDs, b: ComplexDs) -> ComplexDs {
+    return ComplexDs(
+        sub_ds(mul_ds(a.x, b.x), mul_ds(a.y, b.y)),
+        add_ds(mul_ds(a.x, b.y), mul_ds(a.y, b.x)));
+}
+
+// z^e for integer e
+fn powds(z: ComplexDs, e: u32) -> ComplexDs {
+    var p = ComplexDs(Ds(1.0, 0.0), Ds(0.0, 0.0));
+    for (var k = 0u; k < e; k++) {
+        p = mulds(p, z);
+    }
+    return p;
+}
+
+fn foldds(z: ComplexDs) -> ComplexDs {
+    var z = z;
+    if (properties.fold & FOLD_ABS_X) != 0u && z.x.x < 0.0 { z.x = -z.x; }
+    if (properties.fold & FOLD_ABS_Y) != 0u && z.y.x < 0.0 { z.y = -z.y; }
+    if (properties.fold & FOLD_CONJUGATE) != 0u { z.y = -z.y; }
+    return z;
+}
+
+// step32 with double-single z, for integer exponents
+fn stepds(z: ptr<function, ComplexDs>, der: ptr<function, Complex32>) {
+    let w = foldds(*z);
+    let p = powds(w, u32(properties.exponent) - 1u);
+    let z_hi = Complex32((*z).x.x, (*z).y.x);
+    *der = properties.exponent * mul32(Complex32(p.x.x, p.y.x), fold_derivative32(z_hi, *der));
+    *z = mulds(p, w);
+    if (properties.fold & FOLD_ABS_RE) != 0u && (*z).x.x < 0.0 {
+        (*z).x = -(*z).x;
+        (*der).x = -(*der).x;
+    }
+}
+
+fn in_cardioid_or_bulbds(c: ComplexDs) -> bool {
+    let x = sub_ds(c.x, Ds(0.25, 0.0));
+    let y2 = mul_ds(c.y, c.y);
+    let q = add_ds(mul_ds(x, x), y2);
+    let cardioid = sub_ds(mul_ds(q, add_ds(q, x)), 0.25 * y2);
+    let x1 = add_ds(c.x, Ds(1.0, 0.0));
+    let bulb = sub_ds(add_ds(mul_ds(x1, x1), y2), Ds(0.0625, 0.0));
+    return cardioid.x <= 0.0 || bulb.x <= 0.0;
+}
+
+// the derivatives dz and dc are as in julia32,
+// their precision only matters for the colour, the exponent must be an integer
+fn juliads(c: ComplexDs, z: ComplexDs, dz: Complex32, dc: f32) -> vec3<f32> {
+    var z = z;
+    var der = dz;
+    var n = 0u;
+    var abs = 0.0;
+    let bailout = properties.bailout * properties.bailout;
+    let epsilon = properties.zoom * PERIODICITY_EPSILON;
+    var saved = z;
+    var period = 0u;
+    var period_limit = 1u;
+    var trap = new_trap();
+    var average = new_average(Complex32(z.x.x, z.y.x));
+
+    while (abs < bailout && n < properties.max_iter) {
+        stepds(&z, &der);
+        der += Complex32(dc, 0.0);
+        z.x = add_ds(z.x, c.x);
+        z.y = add_ds(z.y, c.y);
+        abs = z.x.x * z.x.x + z.y.x * z.y.x;
+        n++;
+        update_trap(&trap, Complex32(z.x.x, z.y.x), n);
+        update_average(&average, Complex32(z.x.x, z.y.x), Complex32(c.x.x, c.y.x));
+
+        // periodicity checking as in julia32
+        if properties.interior_checks != 0u {
+            let d = Complex32(sub_ds(z.x, saved.x).x, sub_ds(z.y, saved.y).x);
+            if dot(d, d) < epsilon * epsilon {
+                n = properties.max_iter;
+                break;
+            }
+            period++;
+            if period == period_limit {
+                saved = z;
+                period = 0u;
+                period_limit *= 2u;
+            }
+        }
+    }
+
+    abs = sqrt(abs);
+
+    if (n == properties.max_iter) {
+        return inside_colour(trap);
+    }
+    else if average_colouring() {
+        return light(average_colour(average, abs, n), Complex32(z.x.x, z.y.x), der);
+    }
+    else {
+        return light(colour(abs, n, distance_estimate(abs, length(der)), trap), Complex32(z.x.x, z.y.x), der);
+    }
+}
+
+fn mandelbrotds(c: ComplexDs) -> vec3<f32> {
+    if check_cardioid_or_bulb() && in_cardioid_or_bulbds(c) {
+        return vec3<f32>(0.0);
+    }
+    return juliads(c, ComplexDs(Ds(0.0, 0.0), Ds(0.0, 0.0)), Complex32(0.0, 0.0), properties.zoom);
+}
+
+fn fractalds(p: ComplexDs) -> vec3<f32> {
+    if properties.julia != 0u {
+        let c = ComplexDs(Ds(properties.julia_c.x, 0.0), Ds(properties.julia_c.y, 0.0));
+        return juliads(c, p, Complex32(properties.zoom, 0.0), 0.0);
+    }
+    return mandelbrotds(p);
+}
+
+// colour at the point of the plane at tex_coords, with the math of properties.math64
+fn colour_at(tex_coords: vec2<f32>) -> vec3<f32> {
+    let u = tex_coords * 2.0 - vec2<f32>(1.0, 1.0);
+
+    if properties.math64 == 2u {
+        return perturbation32(u);
+    }
+    else if properties.math64 != 0u {
+        // the offset from the center is small enough for f32
+        let offset = u * properties.zoom;
+        let c = ComplexDs(
+            add_ds(Ds(properties.center.x, properties.center_lo.x), Ds(offset.x, 0.0)),
+            add_ds(Ds(properties.center.y, properties.center_lo.y), Ds(offset.y, 0.0)));
+        return fractalds(c);
+    }
+    else {
+        let c = u * properties.zoom + properties.center;
+        return fractal32(c);
+    }
+}
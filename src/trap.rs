@@ -0,0 +1,152 @@
+//! Orbit traps.
+//!
+//! A trap is a shape in the plane of `z`, and the trap colourings colour a point
+//! by how close its orbit comes to it: by the smallest distance of any point of
+//! the orbit, or by the iteration at which the orbit first comes within the
+//! width of the trap. The orbits inside the set are coloured too, their points
+//! stay near the trap for as long as they are iterated.
+
+use std::{fmt, str::FromStr};
+
+/// Values of `Properties::trap`.
+/// The center of the trap.
+pub const TRAP_POINT: u32 = 0;
+/// The line through the center at the angle of the trap.
+pub const TRAP_LINE: u32 = 1;
+/// The line and the one perpendicular to it through the center.
+pub const TRAP_CROSS: u32 = 2;
+/// The circle with the radius of the trap around the center.
+pub const TRAP_CIRCLE: u32 = 3;
+/// Pickover stalks, the horizontal and the vertical line through the center.
+pub const TRAP_STALK: u32 = 4;
+
+/// Names of the shapes, in the order of their values.
+const SHAPES: [&str; 5] = ["point", "line", "cross", "circle", "stalk"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trap {
+    /// One of the `TRAP_*` constants.
+    pub shape: u32,
+    pub center: [f32; 2],
+    /// Angle of the line and the cross in radians.
+    pub angle: f32,
+    pub radius: f32,
+    /// How close an orbit has to come to hit the trap,
+    /// the colouring by the distance fades out over it.
+    pub width: f32,
+}
+
+impl Trap {
+    pub fn shape_str(&self) -> &'static str {
+        SHAPES.get(self.shape as usize).copied().unwrap_or("unknown")
+    }
+}
+
+impl Default for Trap {
+    fn default() -> Self {
+        Trap {
+            shape: TRAP_POINT,
+            center: [0.0, 0.0],
+            angle: 0.0,
+            radius: 0.5,
+            width: 0.5,
+        }
+    }
+}
+
+impl fmt::Display for Trap {
+    /// Formats as typed into the prompt: the shape, the center, the angle in degrees,
+    /// the radius and the width.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {} {} {} {}",
+            self.shape_str(), self.center[0], self.center[1],
+            self.angle.to_degrees(), self.radius, self.width)
+    }
+}
+
+impl FromStr for Trap {
+    type Err = String;
+
+    /// Parses `shape [x y [angle [radius [width]]]]`, with the angle in degrees
+    /// and the defaults for what is left out.
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut words = s.split_whitespace();
+        let shape = words.next().ok_or("expected: shape [x y [angle [radius [width]]]]")?;
+        let shape = SHAPES.iter()
+            .position(|&name| name == shape)
+            .ok_or_else(|| format!("unknown trap {shape}, expected one of {}", SHAPES.join(", ")))?;
+
+        let numbers = words
+            .map(|word| word.parse::<f32>()
+                .ok()
+                .filter(|x| x.is_finite())
+                .ok_or_else(|| format!("invalid number: {word}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        if numbers.len() == 1 || numbers.len() > 5 {
+            return Err("expected: shape [x y [angle [radius [width]]]]".to_string());
+        }
+
+        let mut trap = Trap { shape: shape as u32, ..Default::default() };
+        if let [x, y, ..] = numbers[..] {
+            trap.center = [x, y];
+        }
+        if let Some(&angle) = numbers.get(2) {
+            trap.angle = angle.to_radians();
+        }
+        if let Some(&radius) = numbers.get(3) {
+            trap.radius = radius;
+        }
+        if let Some(&width) = numbers.get(4) {
+            trap.width = width;
+        }
+
+        if trap.radius < 0.0 {
+            Err("the radius cannot be negative".to_string())
+        }
+        else if trap.width <= 0.0 {
+            Err("the width must be positive".to_string())
+        }
+        else {
+            Ok(trap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_fill_in() {
+        assert_eq!("circle".parse(), Ok(Trap { shape: TRAP_CIRCLE, ..Default::default() }));
+        let trap: Trap = "line 1 -2 90".parse().unwrap();
+        assert_eq!((trap.shape, trap.center, trap.radius), (TRAP_LINE, [1.0, -2.0], 0.5));
+        assert!((trap.angle - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(Trap::from_str("").is_err());
+        assert!(Trap::from_str("square").is_err());
+        assert!(Trap::from_str("point 1").is_err());
+        assert!(Trap::from_str("point 1 x").is_err());
+        assert!(Trap::from_str("point 1 inf").is_err());
+        assert!(Trap::from_str("cross 0 0 45 1 1").is_ok());
+        assert!(Trap::from_str("cross 0 0 45 1 1 1").is_err());
+        assert!(Trap::from_str("circle 0 0 0 -1").is_err());
+        assert!(Trap::from_str("circle 0 0 0 0").is_ok());
+        assert!(Trap::from_str("stalk 0 0 0 1 0").is_err());
+        assert!(Trap::from_str("stalk 0 0 0 1 -1").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let trap = Trap { shape: TRAP_CROSS, center: [-0.75, 0.125], angle: 0.3, radius: 1.5, width: 0.01 };
+        let parsed: Trap = trap.to_string().parse().unwrap();
+        assert_eq!((parsed.shape, parsed.center, parsed.radius, parsed.width), (trap.shape, trap.center, trap.radius, trap.width));
+        assert!((parsed.angle - trap.angle).abs() < 1e-6);
+        for shape in SHAPES {
+            assert_eq!(shape.parse::<Trap>().unwrap().shape_str(), shape);
+        }
+    }
+}